@@ -0,0 +1,137 @@
+//! Per-message cost of encoding the request and response metadata, as
+//! MessagePack and as the JSON that version 1 used. Run with
+//! `cargo bench -p js-abi`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use js_abi::{Headers, PhaseAbi, RequestAbi, ResponseAbi, StartAbi};
+use serde::{de::DeserializeOwned, Serialize};
+
+const ITERATIONS: u32 = 100_000;
+
+fn headers() -> Headers {
+    [
+        ("host", "api.example.com"),
+        (
+            "user-agent",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0",
+        ),
+        ("accept", "application/json, text/plain, */*"),
+        ("accept-language", "en-US,en;q=0.5"),
+        ("accept-encoding", "gzip, deflate, br, zstd"),
+        (
+            "authorization",
+            "Bearer eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJzdWIiOiIxMjM0In0",
+        ),
+        ("content-type", "application/json"),
+        ("content-length", "512"),
+        ("origin", "https://app.example.com"),
+        ("referer", "https://app.example.com/dashboard"),
+        ("cookie", "session=3f9a1c; theme=dark"),
+        ("x-request-id", "b6a0e2a4-5c1d-4f3e-9d7a-1b2c3d4e5f60"),
+        ("x-forwarded-for", "203.0.113.7, 198.51.100.2"),
+        ("x-forwarded-proto", "https"),
+        (
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+fn request() -> RequestAbi {
+    RequestAbi {
+        method: "POST".to_string(),
+        url: "/v1/orders?expand=items&limit=20".to_string(),
+        proto: "HTTP/1.1".to_string(),
+        proto_major: 1,
+        proto_minor: 1,
+        header: headers(),
+        host: "api.example.com".to_string(),
+        remote_addr: "203.0.113.7:51234".to_string(),
+        content_len: 512,
+    }
+}
+
+fn response() -> ResponseAbi {
+    ResponseAbi {
+        status_code: 200,
+        content_len: 2048,
+        header: [
+            ("content-type", "application/json"),
+            ("content-length", "2048"),
+            ("cache-control", "private, max-age=0"),
+            ("date", "Sat, 17 Oct 2026 12:00:00 GMT"),
+            ("server", "envoy"),
+            ("x-request-id", "b6a0e2a4-5c1d-4f3e-9d7a-1b2c3d4e5f60"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect(),
+    }
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    // Warm up caches and the allocator before measuring.
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn bench<T: Serialize + DeserializeOwned>(name: &str, message: &T) {
+    let msgpack = js_abi::encode(message).unwrap();
+    let json = serde_json::to_vec(message).unwrap();
+
+    let msgpack_encode = time(|| {
+        black_box(js_abi::encode(black_box(message)).unwrap());
+    });
+    let msgpack_decode = time(|| {
+        black_box(js_abi::decode::<T>(black_box(&msgpack)).unwrap());
+    });
+    let json_encode = time(|| {
+        black_box(serde_json::to_vec(black_box(message)).unwrap());
+    });
+    let json_decode = time(|| {
+        black_box(serde_json::from_slice::<T>(black_box(&json)).unwrap());
+    });
+
+    println!(
+        "{name:<12} msgpack {:>5} B  encode {:>8?}  decode {:>8?}",
+        msgpack.len(),
+        msgpack_encode,
+        msgpack_decode
+    );
+    println!(
+        "{:<12} json    {:>5} B  encode {:>8?}  decode {:>8?}",
+        "",
+        json.len(),
+        json_encode,
+        json_decode
+    );
+}
+
+fn main() {
+    bench(
+        "StartABI",
+        &StartAbi {
+            request: request(),
+            backend_mode: false,
+        },
+    );
+    bench("ResponseABI", &response());
+    bench(
+        "PhaseABI",
+        &PhaseAbi {
+            request: request(),
+            response: response(),
+        },
+    );
+}
@@ -0,0 +1,5 @@
+//! Prints the prelude's TypeScript view of the ABI, see `js_abi::typescript`.
+
+fn main() {
+    print!("{}", js_abi::typescript());
+}
@@ -0,0 +1,238 @@
+//! The interface between modules built by `apoxy-js` and the host that runs
+//! them.
+//!
+//! Modules import their request/response primitives from `extism:host/user`
+//! and exchange the messages below with them, encoded with [`encode`]. Bodies
+//! travel separately, as their own Extism memory blocks. The prelude's view
+//! of the messages is generated from these types, see [`typescript`].
+//!
+//! Hosts call `_apoxy_abi` on a module before anything else to learn which
+//! [`VERSION`] it speaks and which [`Feature`]s it supports. Its answer is
+//! JSON, so that hosts can read it whatever the version.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[macro_use]
+mod typescript;
+
+pub use typescript::{typescript, TypeScript};
+
+/// Bumped on any change that breaks existing modules or hosts. Additions
+/// that either side can do without are announced as a [`Feature`] instead.
+/// Version 1 exchanged the request and response metadata as JSON and had no
+/// logging, secrets or KV imports.
+pub const VERSION: u32 = 2;
+
+/// Returned by host functions that succeed without returning a block.
+pub const OK: u64 = 0;
+/// Returned by host functions that fail without returning a block.
+pub const FAILED: u64 = 1;
+/// The offset that stands for no memory block: an empty body, the end of a
+/// body being read, or no fetch in flight. `_apoxy_fetch_start` returns it
+/// when the fetch couldn't be started, as handles start at 1.
+pub const NONE: u64 = 0;
+
+/// Encodes a message for the other side. Every message, in either direction,
+/// is MessagePack with named fields, which is what the engine's transcoder
+/// produces from JavaScript objects.
+pub fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(message)
+}
+
+/// Decodes a message from the other side, see [`encode`].
+pub fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, rmp_serde::decode::Error> {
+    rmp_serde::from_slice(bytes)
+}
+
+/// Header names and values in order. Names may repeat, as with `Set-Cookie`,
+/// and are compared case-insensitively.
+pub type Headers = Vec<(String, String)>;
+
+/// Optional parts of the interface, reported by `_apoxy_abi`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// Response bodies sent in chunks with `_apoxy_resp_stream`,
+    /// `_apoxy_resp_write` and `_apoxy_resp_close`.
+    StreamingResponses,
+    /// Concurrent fetches through `_apoxy_fetch_start` and `_apoxy_fetch_wait`.
+    AsyncFetch,
+    /// The `_apoxy_on_request`, `_apoxy_on_response` and `_apoxy_on_complete`
+    /// exports, and `_apoxy_phases` to list the registered ones.
+    Phases,
+    /// A feature from a newer version that this side doesn't know.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Feature {
+    /// Everything this version of the interface defines.
+    pub const ALL: &'static [Feature] = &[
+        Feature::StreamingResponses,
+        Feature::AsyncFetch,
+        Feature::Phases,
+    ];
+}
+
+/// What `_apoxy_abi` returns, as JSON.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AbiInfo {
+    pub version: u32,
+    pub features: Vec<Feature>,
+}
+
+impl AbiInfo {
+    /// The interface as implemented by this crate version.
+    pub fn current() -> Self {
+        Self {
+            version: VERSION,
+            features: Feature::ALL.to_vec(),
+        }
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+message! {
+    /// The request metadata: the downstream request handed to the module, or
+    /// a request it sends upstream.
+    "RequestABI" struct RequestAbi {
+        pub method: String,
+        pub url: String,
+        pub proto: String,
+        pub proto_major: u32,
+        pub proto_minor: u32,
+        pub header: Headers,
+        pub host: String,
+        pub remote_addr: String,
+        pub content_len: usize,
+    }
+}
+
+message! {
+    /// The response metadata. `content_len` is 0 for a response whose body is
+    /// streamed.
+    "ResponseABI" struct ResponseAbi {
+        pub status_code: u16,
+        pub content_len: usize,
+        pub header: Headers,
+    }
+}
+
+message! {
+    /// The input of `_apoxy_start` and `_apoxy_on_request`.
+    "StartABI" struct StartAbi extends RequestAbi as request {
+        pub backend_mode: bool,
+    }
+}
+
+message! {
+    /// The input of `_apoxy_on_response` and `_apoxy_on_complete`: the
+    /// request as forwarded and the head of the response.
+    "PhaseABI" struct PhaseAbi extends RequestAbi as request {
+        pub response: ResponseAbi,
+    }
+}
+
+/// Severity of a [`LogRecord`], from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/// Arbitrary key/value pairs attached to a [`LogRecord`].
+pub type LogFields = BTreeMap<String, serde_json::Value>;
+
+message! {
+    /// A structured log record written with `Apoxy.log` and passed to
+    /// `_apoxy_log`. Hosts emit it as a JSON object with these keys.
+    "LogRecordABI" struct LogRecord {
+        pub level: LogLevel,
+        pub message: String,
+        /// The `x-request-id` of the request being handled, if any.
+        pub request_id: Option<String>,
+        pub fields: LogFields,
+    }
+}
+
+/// The request passed to `_apoxy_fetch_start`, with the body as a separate
+/// block.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FetchRequest {
+    pub url: String,
+    pub method: String,
+    pub headers: Headers,
+}
+
+/// The response returned from `_apoxy_fetch_wait` for the fetch started with
+/// `handle`. The body is the block at `body_offset`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FetchResponse {
+    pub handle: u64,
+    pub status: u16,
+    pub headers: Headers,
+    pub body_offset: u64,
+    pub error: Option<String>,
+}
+
+/// The write passed to `_apoxy_kv_put`, with the value as a separate block.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KvPut {
+    pub key: String,
+    /// Seconds until the key expires, or `None` to keep it.
+    pub expiration_ttl: Option<u64>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// What `_apoxy_kv_get` returns for a key that is set. The value is the block
+/// at `value_offset`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KvValue {
+    pub value_offset: u64,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// The query passed to `_apoxy_kv_list`. Keys are listed in order, starting
+/// after `cursor`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KvList {
+    pub prefix: String,
+    pub limit: u32,
+    pub cursor: Option<String>,
+}
+
+/// A key returned by `_apoxy_kv_list`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KvKey {
+    pub name: String,
+    /// When the key expires, in seconds since the Unix epoch.
+    pub expiration: Option<u64>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// What `_apoxy_kv_list` returns. `cursor` continues the listing, and is
+/// `None` once every matching key was returned.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KvListResult {
+    pub keys: Vec<KvKey>,
+    pub cursor: Option<String>,
+}
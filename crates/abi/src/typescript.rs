@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use crate::{LogLevel, LogRecord, PhaseAbi, RequestAbi, ResponseAbi, StartAbi};
+
+/// The TypeScript type a value is decoded to in the prelude.
+pub trait TypeScript {
+    fn ts_type() -> String;
+}
+
+/// A message with a TypeScript interface, declared with `message!`.
+pub trait Interface {
+    fn ts_interface() -> String;
+}
+
+macro_rules! primitive {
+    ($($ty:ty => $ts:literal),*) => {
+        $(
+            impl TypeScript for $ty {
+                fn ts_type() -> String {
+                    $ts.to_string()
+                }
+            }
+        )*
+    };
+}
+
+primitive!(
+    String => "string",
+    bool => "boolean",
+    u16 => "number",
+    u32 => "number",
+    u64 => "number",
+    usize => "number"
+);
+
+impl<T: TypeScript> TypeScript for Option<T> {
+    fn ts_type() -> String {
+        format!("{} | null", T::ts_type())
+    }
+}
+
+impl<T: TypeScript> TypeScript for Vec<T> {
+    fn ts_type() -> String {
+        format!("{}[]", T::ts_type())
+    }
+}
+
+impl<V: TypeScript> TypeScript for BTreeMap<String, V> {
+    fn ts_type() -> String {
+        format!("Record<string, {}>", V::ts_type())
+    }
+}
+
+impl TypeScript for serde_json::Value {
+    fn ts_type() -> String {
+        "unknown".to_string()
+    }
+}
+
+impl TypeScript for LogLevel {
+    fn ts_type() -> String {
+        "LogLevelABI".to_string()
+    }
+}
+
+impl<A: TypeScript, B: TypeScript> TypeScript for (A, B) {
+    fn ts_type() -> String {
+        format!("[{}, {}]", A::ts_type(), B::ts_type())
+    }
+}
+
+/// Declares a message and its TypeScript interface. A message that
+/// `extends` another has the other's fields flattened into it.
+macro_rules! message {
+    (
+        $(#[doc = $doc:literal])*
+        $ts:literal struct $name:ident $(extends $parent:ident as $field:ident)? {
+            $(
+                $(#[doc = $field_doc:literal])*
+                pub $field_name:ident: $field_ty:ty,
+            )*
+        }
+    ) => {
+        $(#[doc = $doc])*
+        #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+        pub struct $name {
+            $(
+                #[serde(flatten)]
+                pub $field: $parent,
+            )?
+            $(
+                $(#[doc = $field_doc])*
+                pub $field_name: $field_ty,
+            )*
+        }
+
+        impl $crate::TypeScript for $name {
+            fn ts_type() -> String {
+                $ts.to_string()
+            }
+        }
+
+        impl $crate::typescript::Interface for $name {
+            fn ts_interface() -> String {
+                let mut ts = String::from("/**\n");
+                $(ts.push_str(&format!(" *{}\n", $doc));)*
+                ts.push_str(" *\n * @internal\n */\n");
+                ts.push_str(concat!("export interface ", $ts));
+                $(
+                    ts.push_str(" extends ");
+                    ts.push_str(&<$parent as $crate::TypeScript>::ts_type());
+                )?
+                ts.push_str(" {\n");
+                $(
+                    ts.push_str(&format!(
+                        "  {}: {};\n",
+                        stringify!($field_name),
+                        <$field_ty as $crate::TypeScript>::ts_type()
+                    ));
+                )*
+                ts.push_str("}\n");
+                ts
+            }
+        }
+    };
+}
+
+/// The TypeScript declarations of the messages, which the `abi-ts`
+/// binary writes to `crates/core/src/prelude/src/abi.ts`.
+pub fn typescript() -> String {
+    let mut ts = String::from(
+        "// Generated from the js-abi crate by `cargo run -p js-abi --bin abi-ts`, do not edit.\n",
+    );
+    ts.push_str(
+        "\n/**\n * Header names and values in order. Names may repeat and are matched\n * case-insensitively; the prelude sends them lowercased.\n *\n * @internal\n */\nexport type HeadersABI = [string, string][];\n",
+    );
+    ts.push_str(
+        "\n/**\n * Severity of a log record.\n *\n * @internal\n */\nexport type LogLevelABI = \"debug\" | \"info\" | \"warn\" | \"error\";\n",
+    );
+    for interface in [
+        RequestAbi::ts_interface(),
+        ResponseAbi::ts_interface(),
+        StartAbi::ts_interface(),
+        PhaseAbi::ts_interface(),
+        LogRecord::ts_interface(),
+    ] {
+        ts.push('\n');
+        ts.push_str(&interface);
+    }
+    ts
+}
@@ -0,0 +1,10 @@
+//! The prelude checks in the generated TypeScript types, so they must be
+//! regenerated whenever the messages change.
+
+#[test]
+fn prelude_types_are_up_to_date() {
+    assert!(
+        js_abi::typescript() == include_str!("../../core/src/prelude/src/abi.ts"),
+        "crates/core/src/prelude/src/abi.ts is out of date, run `cargo run -p js-abi --bin abi-ts > crates/core/src/prelude/src/abi.ts`"
+    );
+}
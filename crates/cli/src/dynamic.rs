@@ -0,0 +1,253 @@
+use anyhow::{Context, Result};
+use wagen::encoder::{self, ExportKind, ExportSection, RawSection};
+use wagen::parser::{Parser, Payload};
+use wagen::{
+    BlockType, Builder, ConstExpr, Instr, Local, MemArg, MemoryType, Module, TypeList, ValType,
+};
+
+/// Starts the name modules built with `--dynamic` import the engine under,
+/// see `provider_name`.
+const PROVIDER_PREFIX: &str = "apoxy_js_core_";
+
+/// The name modules built with `--dynamic` import `core` under. The modules
+/// hold bytecode only the QuickJS build in that exact engine can read, so the
+/// name is derived from the engine itself, which also changes with the ABI
+/// the exports below speak. Hosts can keep serving older modules from the
+/// provider they were built against.
+pub(crate) fn provider_name(core: &[u8]) -> String {
+    // FNV-1a, which is stable across Rust releases unlike `DefaultHasher`.
+    let hash = core.iter().fold(0xcbf29ce484222325u64, |hash, &x| {
+        (hash ^ x as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{}{:016x}", PROVIDER_PREFIX, hash)
+}
+
+/// Engine exports that a dynamic module forwards, after loading its code.
+const FORWARDED: &[&str] = &[
+    "_apoxy_start",
+    "_apoxy_sdk_v1alpha",
+    "_apoxy_abi",
+    "_apoxy_phases",
+    "_apoxy_on_request",
+    "_apoxy_on_response",
+    "_apoxy_on_complete",
+];
+
+const PAGE_SIZE: usize = 65536;
+
+/// Builds a module holding only `user_code` that imports the engine `core`
+/// under its `provider_name`. On the first call it copies the code into
+/// Extism memory and hands it to the engine's `_apoxy_load`, which
+/// evaluates it.
+pub(crate) fn module(core: &[u8], user_code: &[u8]) -> Result<Vec<u8>> {
+    let provider = provider_name(core);
+    let mut module = Module::new();
+    let extism = module.link_extism();
+    let load = module.import(
+        &provider,
+        "_apoxy_load",
+        None,
+        [ValType::I64],
+        [ValType::I32],
+    );
+    // Functions are numbered after all imports, so declare those first.
+    let forwards = FORWARDED
+        .iter()
+        .map(|name| {
+            let import = module.import(&provider, name, None, [], [ValType::I32]);
+            (*name, import)
+        })
+        .collect::<Vec<_>>();
+
+    module.memory(MemoryType {
+        minimum: user_code.len().div_ceil(PAGE_SIZE).max(1) as u64,
+        maximum: None,
+        memory64: false,
+        shared: false,
+    });
+    module.data_segment(&ConstExpr::i32_const(0), user_code);
+    let loaded = module
+        .global("loaded", ValType::I32, true, &ConstExpr::i32_const(0))
+        .clone();
+    let load_result = module
+        .global("load_result", ValType::I32, true, &ConstExpr::i32_const(0))
+        .clone();
+    let length = user_code.len() as i64;
+
+    let mut locals = TypeList::<Local>::new();
+    let offset = locals.push(ValType::I64);
+    let index = locals.push(ValType::I64);
+    let ensure_loaded = module
+        .func("ensure_loaded", [], [ValType::I32], locals)
+        .with_builder(|b| {
+            b.if_then(BlockType::Empty, loaded.clone(), |b: &mut Builder| {
+                b.push(load_result.clone()).return_();
+            })
+            .push(length)
+            .push(extism.alloc)
+            .push(offset.set())
+            // Copy whole words while they fit, then the remaining bytes.
+            .block(BlockType::Empty, |b: &mut Builder| {
+                b.loop_(BlockType::Empty, |b: &mut Builder| {
+                    b.push(index)
+                        .push(8i64)
+                        .push(Instr::I64Add)
+                        .push(length)
+                        .push([Instr::I64GtU, Instr::BrIf(1)])
+                        .push(offset)
+                        .push(index)
+                        .push(Instr::I64Add)
+                        .push(index)
+                        .push(Instr::I32WrapI64)
+                        .push(Instr::I64Load(mem_arg(3)))
+                        .push(extism.store_u64)
+                        .push(index)
+                        .push(8i64)
+                        .push(Instr::I64Add)
+                        .push(index.set())
+                        .push(Instr::Br(0));
+                });
+            })
+            .block(BlockType::Empty, |b: &mut Builder| {
+                b.loop_(BlockType::Empty, |b: &mut Builder| {
+                    b.push(index)
+                        .push(length)
+                        .push([Instr::I64GeU, Instr::BrIf(1)])
+                        .push(offset)
+                        .push(index)
+                        .push(Instr::I64Add)
+                        .push(index)
+                        .push(Instr::I32WrapI64)
+                        .push(Instr::I32Load8U(mem_arg(0)))
+                        .push(extism.store_u8)
+                        .local_incr(index, ValType::I64)
+                        .push(Instr::Br(0));
+                });
+            })
+            // The engine doesn't evaluate the code twice, so a failed load
+            // isn't retried either. Every later call returns its error code
+            // instead of running the engine without the user's code.
+            .push(offset)
+            .push(load)
+            .push(load_result.set())
+            .push(1i32)
+            .push(loaded.set())
+            .push(load_result.clone());
+        })
+        .index();
+
+    module
+        .func("_start", [], [ValType::I32], [])
+        .with_builder(|b| {
+            b.push(ensure_loaded);
+        })
+        .export("_start");
+
+    for (name, forward) in forwards {
+        let mut locals = TypeList::<Local>::new();
+        let rc = locals.push(ValType::I32);
+        module
+            .func(name, [], [ValType::I32], locals)
+            .with_builder(|b| {
+                b.if_then(
+                    BlockType::Empty,
+                    |b: &mut Builder| {
+                        b.push(ensure_loaded).push(rc.tee());
+                    },
+                    |b: &mut Builder| {
+                        b.push(rc).return_();
+                    },
+                )
+                .push(forward);
+            })
+            .export(name);
+    }
+
+    module
+        .validate()
+        .context("Failed to generate the dynamic module")
+}
+
+/// Returns the engine as a provider for dynamic modules. Wasmtime starts a
+/// fresh instance for every call into a linked module that exports `_start`,
+/// which would drop the loaded code, so that export is removed.
+pub(crate) fn provider(core: &[u8]) -> Result<Vec<u8>> {
+    let mut wasm = encoder::Module::new();
+    for payload in Parser::new(0).parse_all(core) {
+        let payload = payload.context("Failed to read the engine")?;
+        if let Payload::ExportSection(reader) = &payload {
+            let mut exports = ExportSection::new();
+            for export in reader.clone() {
+                let export = export?;
+                if export.name != "_start" {
+                    exports.export(export.name, ExportKind::from(export.kind), export.index);
+                }
+            }
+            wasm.section(&exports);
+        } else if let Some((id, range)) = payload.as_section() {
+            wasm.section(&RawSection {
+                id,
+                data: &core[range],
+            });
+        }
+    }
+    Ok(wasm.finish())
+}
+
+/// The provider a dynamic module imports the engine from, if `wasm` is one.
+pub(crate) fn provider_import(wasm: &[u8]) -> Option<String> {
+    Parser::new(0)
+        .parse_all(wasm)
+        .find_map(|payload| match payload {
+            Ok(Payload::ImportSection(reader)) => reader.into_iter().find_map(|import| {
+                let import = import.ok()?;
+                import
+                    .module
+                    .starts_with(PROVIDER_PREFIX)
+                    .then(|| import.module.to_string())
+            }),
+            _ => None,
+        })
+}
+
+fn mem_arg(align: u32) -> MemArg {
+    MemArg {
+        offset: 0,
+        align,
+        memory_index: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use extism::{Manifest, Plugin, Wasm};
+
+    /// A provider whose `_apoxy_load` fails and whose other exports succeed.
+    fn provider() -> Vec<u8> {
+        let forwards: String = FORWARDED
+            .iter()
+            .map(|name| format!(r#"(func (export "{}") (result i32) (i32.const 0))"#, name))
+            .collect();
+        format!(
+            r#"(module
+                 (func (export "_apoxy_load") (param i64) (result i32) (i32.const 1))
+                 {})"#,
+            forwards
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn keeps_failing_after_a_failed_load() {
+        let core = provider();
+        let manifest = Manifest::new([
+            Wasm::data(core.clone()).with_name(provider_name(&core)),
+            Wasm::data(module(&core, b"throw 1").unwrap()).with_name("main"),
+        ]);
+        let mut plugin = Plugin::new(&manifest, [], true).unwrap();
+        for _ in 0..2 {
+            assert!(plugin.call::<&[u8], &[u8]>("_apoxy_start", &[]).is_err());
+        }
+    }
+}
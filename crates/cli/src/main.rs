@@ -1,14 +1,16 @@
+mod dynamic;
 mod opt;
 mod options;
+mod run;
+mod serve;
+mod source;
+mod sourcemap;
 
-use crate::options::Options;
-use anyhow::{bail, Result};
+use crate::options::{Command as Subcommand, Options};
+use anyhow::{bail, Context, Result};
 use log::LevelFilter;
-use std::env;
-use std::process::Stdio;
-use std::{fs, io::Write, process::Command};
+use std::fs;
 use structopt::StructOpt;
-use tempfile::TempDir;
 
 const CORE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/engine.wasm"));
 
@@ -20,65 +22,66 @@ fn main() -> Result<()> {
         .init();
 
     let opts = Options::from_args();
-    if opts.core {
-        opt::Optimizer::new(CORE)
-            .wizen(true)
-            .write_optimized_wasm(opts.output)?;
-        return Ok(());
+    match opts.command {
+        Some(Subcommand::Run(run_opts)) => return run::run(run_opts),
+        Some(Subcommand::Serve(serve_opts)) => return serve::serve(serve_opts),
+        Some(Subcommand::Provider(provider_opts)) => {
+            let name = dynamic::provider_name(CORE);
+            let output = provider_opts
+                .output
+                .unwrap_or_else(|| format!("{}.wasm", name).into());
+            fs::write(&output, dynamic::provider(CORE)?)
+                .with_context(|| format!("Failed to write {}", output.display()))?;
+            eprintln!("Provider: {}", name);
+            return opt::optimize(&output);
+        }
+        None => {}
     }
 
-    // Copy in the user's js code from the configured file
-    let user_code = fs::read(&opts.input_js)?;
-
-    // Create a tmp dir to hold all the library objects
-    // This can go away once we do all the wasm-merge stuff in process
-    let tmp_dir = TempDir::new()?;
-    let core_path = tmp_dir.path().join("core.wasm");
+    let Some(input_js) = opts.input_js else {
+        bail!("No input file given, see `apoxy-js --help`");
+    };
 
-    // First wizen the core module
-    let self_cmd = env::args().next().expect("Expected a command argument");
-    {
-        let mut command = Command::new(self_cmd)
-            .arg("-c")
-            .arg(&opts.input_js)
-            .arg("-o")
-            .arg(&core_path)
-            .stdin(Stdio::piped())
-            .spawn()?;
-        command
-            .stdin
-            .take()
-            .expect("Expected to get writeable stdin")
-            .write_all(&user_code)?;
-        let status = command.wait()?;
-        if !status.success() {
-            bail!("Couldn't create wasm from input");
-        }
-    }
+    // Collect the user's js code, along with any modules it imports
+    let mut source = source::Source::load(&input_js, opts.source_map.as_deref())?;
+    // A dynamic module's code is evaluated when it's loaded, not snapshotted.
+    source.initialize = !opts.defer_init && !opts.dynamic;
+    let mut user_code = source.to_bytes()?;
 
-    let output = Command::new("wasm-merge")
-        .arg("--version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
-    if output.is_err() {
-        bail!("Failed to detect wasm-merge. Please install binaryen and make sure wasm-merge is on your path: https://github.com/WebAssembly/binaryen");
+    // Compile to bytecode on the engine itself, where QuickJS lives
+    if !opts.keep_source {
+        user_code = js_host::call(CORE, "_apoxy_compile", &user_code)
+            .context("Failed to compile the code to bytecode")?;
     }
 
-    // Merge the shim with the core module
-    let status = Command::new("wasm-merge")
-        .arg(&core_path)
-        .arg("core")
-        .arg("-o")
-        .arg(&opts.output)
-        .arg("--enable-reference-types")
-        .arg("--enable-bulk-memory")
-        .status()?;
-    if !status.success() {
-        bail!("wasm-merge failed. Couldn't merge shim");
+    let wasm = opt::wizen(CORE, &user_code)?;
+    if opts.dynamic {
+        // The snapshot was only taken to check the code for a handler.
+        fs::write(&opts.output, dynamic::module(CORE, &user_code)?)
+            .with_context(|| format!("Failed to write {}", opts.output.display()))?;
+        return Ok(());
     }
-
-    opt::optimize_wasm_file(opts.output)?;
+    fs::write(&opts.output, wasm)
+        .with_context(|| format!("Failed to write {}", opts.output.display()))?;
+    opt::optimize(&opts.output)?;
 
     Ok(())
 }
+
+/// Links the engine into modules built with `--dynamic` for `run` and `serve`.
+pub(crate) fn provider_for(wasm: &[u8]) -> Result<Option<js_host::Provider>> {
+    let Some(name) = dynamic::provider_import(wasm) else {
+        return Ok(None);
+    };
+    if name != dynamic::provider_name(CORE) {
+        bail!(
+            "The module imports the engine as {}, but this apoxy-js provides {}. Rebuild the module with this apoxy-js.",
+            name,
+            dynamic::provider_name(CORE)
+        );
+    }
+    Ok(Some(js_host::Provider {
+        name,
+        wasm: dynamic::provider(CORE)?,
+    }))
+}
@@ -1,71 +1,37 @@
-use anyhow::{Error, Result};
-use std::{
-    path::Path,
-    process::{Command, Stdio},
-};
-use wizer::Wizer;
-
-pub(crate) struct Optimizer<'a> {
-    wizen: bool,
-    optimize: bool,
-    wasm: &'a [u8],
-}
-
-impl<'a> Optimizer<'a> {
-    pub fn new(wasm: &'a [u8]) -> Self {
-        Self {
-            wasm,
-            optimize: false,
-            wizen: false,
-        }
-    }
+use std::fs;
+use std::path::Path;
 
-    #[allow(unused)]
-    pub fn optimize(self, optimize: bool) -> Self {
-        Self { optimize, ..self }
-    }
-
-    pub fn wizen(self, wizen: bool) -> Self {
-        Self { wizen, ..self }
-    }
-
-    pub fn write_optimized_wasm(self, dest: impl AsRef<Path>) -> Result<(), Error> {
-        if self.wizen {
-            let wasm = Wizer::new()
-                .allow_wasi(true)?
-                .inherit_stdio(true)
-                .wasm_bulk_memory(true)
-                .run(self.wasm)?;
-            std::fs::write(&dest, wasm)?;
-        } else {
-            std::fs::write(&dest, self.wasm)?;
-        }
-
-        if self.optimize {
-            optimize_wasm_file(dest)?;
-        }
+use anyhow::{Context, Result};
+use tempfile::TempDir;
+use wasm_opt::{Feature, OptimizationOptions, Pass};
+use wizer::Wizer;
 
-        Ok(())
-    }
+/// Where `wizer.initialize` in the core reads the user's code from.
+const GUEST_DIR: &str = "/apoxy";
+const SOURCE_FILE: &str = "source";
+
+/// Runs the engine's `wizer.initialize` with `user_code` and returns the
+/// snapshot, which is the finished module before optimization.
+pub(crate) fn wizen(core: &[u8], user_code: &[u8]) -> Result<Vec<u8>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join(SOURCE_FILE), user_code)?;
+
+    Wizer::new()
+        .allow_wasi(true)?
+        .inherit_stdio(true)
+        .map_dir(GUEST_DIR, dir.path())
+        .wasm_bulk_memory(true)
+        .run(core)
+        .context("Couldn't initialize the module with the user's code")
 }
 
-pub(crate) fn optimize_wasm_file(dest: impl AsRef<Path>) -> Result<(), Error> {
-    let output = Command::new("wasm-opt")
-        .arg("--version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
-    if output.is_err() {
-        anyhow::bail!("Failed to detect wasm-opt. Please install binaryen and make sure wasm-opt is on your path: https://github.com/WebAssembly/binaryen");
-    }
-    Command::new("wasm-opt")
-        .arg("--enable-reference-types")
-        .arg("--enable-bulk-memory")
-        .arg("--strip")
-        .arg("-O3")
-        .arg(dest.as_ref())
-        .arg("-o")
-        .arg(dest.as_ref())
-        .status()?;
-    Ok(())
+/// Optimizes the module at `path` in place with Binaryen.
+pub(crate) fn optimize(path: &Path) -> Result<()> {
+    OptimizationOptions::new_opt_level_3()
+        .enable_feature(Feature::ReferenceTypes)
+        .enable_feature(Feature::BulkMemory)
+        .add_pass(Pass::StripDebug)
+        .debug_info(false)
+        .run(path, path)
+        .with_context(|| format!("Couldn't optimize {}", path.display()))
 }
@@ -4,12 +4,111 @@ use structopt::StructOpt;
 #[derive(Debug, StructOpt)]
 #[structopt(name = "apoxy-js", about = "Apoxy JavaScript Edge Function Compiler")]
 pub struct Options {
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+
     #[structopt(parse(from_os_str))]
-    pub input_js: PathBuf,
+    pub input_js: Option<PathBuf>,
 
     #[structopt(short = "o", parse(from_os_str), default_value = "index.wasm")]
     pub output: PathBuf,
 
-    #[structopt(short = "c")]
-    pub core: bool,
+    /// Source map of the input, when it has no `sourceMappingURL` comment
+    #[structopt(long = "source-map", parse(from_os_str))]
+    pub source_map: Option<PathBuf>,
+
+    /// Run top-level code when the module starts instead of while building it
+    #[structopt(long = "defer-init")]
+    pub defer_init: bool,
+
+    /// Embed the source text instead of QuickJS bytecode, for debugging
+    #[structopt(long = "keep-source")]
+    pub keep_source: bool,
+
+    /// Emit a small module that imports the engine from a shared provider,
+    /// see `apoxy-js provider`
+    #[structopt(long = "dynamic")]
+    pub dynamic: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Run a compiled module against a scripted request, without the Apoxy proxy
+    Run(RunOptions),
+    /// Serve a compiled module over HTTP for local development
+    Serve(ServeOptions),
+    /// Write the engine that modules built with `--dynamic` link against
+    Provider(ProviderOptions),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ProviderOptions {
+    /// Defaults to the name dynamic modules import the engine under, plus `.wasm`
+    #[structopt(short = "o", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RunOptions {
+    #[structopt(parse(from_os_str))]
+    pub wasm: PathBuf,
+
+    /// JSON file describing the request, the upstream response and stubbed fetches
+    #[structopt(short = "s", long = "scenario", parse(from_os_str))]
+    pub scenario: Option<PathBuf>,
+
+    /// Run the handler as a backend instead of a filter
+    #[structopt(long = "backend")]
+    pub backend: bool,
+
+    /// Config values exposed through `Apoxy.env`, as KEY=VALUE
+    #[structopt(long = "config")]
+    pub config: Vec<String>,
+
+    /// Secrets exposed through `Apoxy.secrets`, as KEY=VALUE
+    #[structopt(long = "secret")]
+    pub secrets: Vec<String>,
+
+    /// Include the message and stack of handler exceptions in error responses
+    #[structopt(long = "dev")]
+    pub dev: bool,
+
+    #[structopt(long = "log-level", default_value = "info")]
+    pub log_level: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ServeOptions {
+    #[structopt(parse(from_os_str))]
+    pub wasm: PathBuf,
+
+    #[structopt(short = "p", long = "port", default_value = "8080")]
+    pub port: u16,
+
+    /// Base URL that `req.next()` and pass-through requests are proxied to
+    #[structopt(
+        short = "u",
+        long = "upstream",
+        default_value = "http://127.0.0.1:3000"
+    )]
+    pub upstream: String,
+
+    /// Run the handler as a backend instead of a filter
+    #[structopt(long = "backend")]
+    pub backend: bool,
+
+    /// Config values exposed through `Apoxy.env`, as KEY=VALUE
+    #[structopt(long = "config")]
+    pub config: Vec<String>,
+
+    /// Secrets exposed through `Apoxy.secrets`, as KEY=VALUE
+    #[structopt(long = "secret")]
+    pub secrets: Vec<String>,
+
+    /// Include the message and stack of handler exceptions in error responses
+    #[structopt(long = "dev")]
+    pub dev: bool,
+
+    #[structopt(long = "log-level", default_value = "info")]
+    pub log_level: String,
 }
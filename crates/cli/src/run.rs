@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use anyhow::{anyhow, bail, Context, Result};
+use js_host::{HttpResponse, Scenario};
+
+use crate::options::RunOptions;
+
+pub(crate) fn run(opts: RunOptions) -> Result<()> {
+    js_host::init_logging(&opts.log_level)?;
+
+    let wasm =
+        fs::read(&opts.wasm).with_context(|| format!("Failed to read {}", opts.wasm.display()))?;
+
+    let mut scenario = match &opts.scenario {
+        Some(path) => {
+            let data =
+                fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_json::from_slice::<Scenario>(&data)
+                .with_context(|| format!("Invalid scenario {}", path.display()))?
+        }
+        None => Scenario::default(),
+    };
+    scenario.backend_mode |= opts.backend;
+    scenario
+        .config
+        .extend(parse_config(&opts.config, opts.dev)?);
+    scenario
+        .secrets
+        .extend(parse_pairs(&opts.secrets, "secret")?);
+
+    let provider = crate::provider_for(&wasm)?;
+    let handled = scenario.run(wasm, provider)?;
+    print_response(&handled.response);
+    match handled.error {
+        Some(e) => bail!("Handler failed: {}", e),
+        None => Ok(()),
+    }
+}
+
+/// Parses `--config` pairs; `--dev` turns on `APOXY_DEV` unless it is set.
+pub(crate) fn parse_config(pairs: &[String], dev: bool) -> Result<BTreeMap<String, String>> {
+    let mut config = parse_pairs(pairs, "config")?;
+    if dev {
+        config
+            .entry("APOXY_DEV".to_string())
+            .or_insert_with(|| "true".to_string());
+    }
+    Ok(config)
+}
+
+/// Parses the KEY=VALUE pairs given to `--{option}`.
+pub(crate) fn parse_pairs(pairs: &[String], option: &str) -> Result<BTreeMap<String, String>> {
+    pairs
+        .iter()
+        .map(|pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid {} {:?}, expected KEY=VALUE", option, pair))?;
+            Ok((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn print_response(resp: &HttpResponse) {
+    println!("HTTP/1.1 {}", resp.status);
+    for (name, value) in &resp.headers {
+        println!("{}: {}", name, value);
+    }
+    println!();
+    println!("{}", String::from_utf8_lossy(&resp.body));
+}
@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+
+use anyhow::{anyhow, Context, Result};
+use js_host::{Host, HttpRequest, HttpResponse, Upstream};
+use log::{error, info};
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::options::ServeOptions;
+use crate::run::{parse_config, parse_pairs};
+
+pub(crate) fn serve(opts: ServeOptions) -> Result<()> {
+    js_host::init_logging(&opts.log_level)?;
+
+    let wasm =
+        fs::read(&opts.wasm).with_context(|| format!("Failed to read {}", opts.wasm.display()))?;
+    let upstream = HttpUpstream {
+        base: opts.upstream.trim_end_matches('/').to_string(),
+    };
+    let provider = crate::provider_for(&wasm)?;
+    let mut host = Host::new(
+        wasm,
+        provider,
+        parse_config(&opts.config, opts.dev)?,
+        parse_pairs(&opts.secrets, "secret")?,
+        Box::new(upstream),
+    )?;
+
+    let server = Server::http(("127.0.0.1", opts.port))
+        .map_err(|e| anyhow!("Failed to listen on port {}: {}", opts.port, e))?;
+    info!(
+        "Serving {} on http://127.0.0.1:{}",
+        opts.wasm.display(),
+        opts.port
+    );
+
+    for mut request in server.incoming_requests() {
+        let resp = convert_request(&mut request)
+            .and_then(|req| host.handle(req, opts.backend))
+            .map(|handled| {
+                if let Some(e) = &handled.error {
+                    error!("{} {}: {}", request.method(), request.url(), e);
+                }
+                handled.response
+            })
+            .unwrap_or_else(|e| {
+                error!("{} {}: {:#}", request.method(), request.url(), e);
+                HttpResponse {
+                    status: 500,
+                    body: format!("{:#}\n", e).into_bytes(),
+                    ..HttpResponse::default()
+                }
+            });
+        info!("{} {} {}", request.method(), request.url(), resp.status);
+
+        if let Err(e) = request.respond(convert_response(resp)) {
+            error!("Failed to write response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn convert_request(request: &mut Request) -> Result<HttpRequest> {
+    let mut body = Vec::new();
+    request.as_reader().read_to_end(&mut body)?;
+
+    let headers = request
+        .headers()
+        .iter()
+        .map(|h| (h.field.to_string(), h.value.to_string()))
+        .collect();
+    let host = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Host"))
+        .map(|h| h.value.to_string())
+        .unwrap_or_default();
+    let version = request.http_version();
+
+    Ok(HttpRequest {
+        method: request.method().to_string(),
+        url: request.url().to_string(),
+        proto: format!("HTTP/{}.{}", version.0, version.1),
+        headers,
+        host,
+        remote_addr: request
+            .remote_addr()
+            .map(|x| x.to_string())
+            .unwrap_or_default(),
+        body,
+    })
+}
+
+fn convert_response(resp: HttpResponse) -> Response<std::io::Cursor<Vec<u8>>> {
+    let mut out = Response::from_data(resp.body).with_status_code(resp.status);
+    for (name, value) in resp.headers {
+        if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            out.add_header(header);
+        }
+    }
+    out
+}
+
+/// Proxies upstream traffic to a local server and outbound `fetch` calls to
+/// the network.
+struct HttpUpstream {
+    base: String,
+}
+
+impl Upstream for HttpUpstream {
+    fn send(&self, req: &HttpRequest) -> Result<HttpResponse> {
+        let url = format!("{}{}", self.base, req.url);
+        http_request(&url, req)
+    }
+
+    fn fetch(&self, req: &HttpRequest) -> Result<HttpResponse> {
+        http_request(&req.url, req)
+    }
+}
+
+fn http_request(url: &str, req: &HttpRequest) -> Result<HttpResponse> {
+    // ureq replaces headers with the same name, so repeated request headers
+    // are combined. Cookies use their own separator.
+    let mut combined: Vec<(String, String)> = Vec::new();
+    for (name, value) in &req.headers {
+        // ureq derives these from the body and the URL.
+        if name.eq_ignore_ascii_case("host") || name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        match combined
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        {
+            Some((k, v)) => {
+                v.push_str(if k.eq_ignore_ascii_case("cookie") {
+                    "; "
+                } else {
+                    ", "
+                });
+                v.push_str(value);
+            }
+            None => combined.push((name.clone(), value.clone())),
+        }
+    }
+    let mut call = ureq::request(&req.method, url);
+    for (name, value) in &combined {
+        call = call.set(name, value);
+    }
+
+    let resp = match call.send_bytes(&req.body) {
+        Ok(resp) => resp,
+        Err(ureq::Error::Status(_, resp)) => resp,
+        Err(e) => return Err(anyhow!("{} {} failed: {}", req.method, url, e)),
+    };
+
+    let status = resp.status();
+    // `headers_names` lists a name once per header line, and `all` returns
+    // every value of a name, so each name is expanded the first time only.
+    let mut seen = HashSet::new();
+    let mut headers = Vec::new();
+    for name in resp.headers_names() {
+        if seen.insert(name.clone()) {
+            for value in resp.all(&name) {
+                headers.push((name.clone(), value.to_string()));
+            }
+        }
+    }
+    let mut body = Vec::new();
+    resp.into_reader().read_to_end(&mut body)?;
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use swc_common::{sync::Lrc, FileName, SourceMap, Span, Spanned};
+use swc_ecma_ast::{EsVersion, ExportSpecifier, ModuleDecl, ModuleExportName, ModuleItem, Program};
+use swc_ecma_parser::lexer::Lexer;
+use swc_ecma_parser::token::{IdentLike, Keyword, KnownIdent, Token, TokenAndSpan, Word};
+use swc_ecma_parser::{Capturing, EsConfig, Parser, StringInput, Syntax};
+
+use crate::sourcemap;
+
+/// The user's code as handed to the core during `wizer.initialize`.
+///
+/// This is serialized with MessagePack and must stay in sync with
+/// `crates/core/src/source.rs`.
+#[derive(Debug, Serialize)]
+pub(crate) struct Source {
+    pub kind: SourceKind,
+    /// For scripts, a single entry. For ES modules, every module reachable
+    /// from the entry point in evaluation order, with the entry point last.
+    pub modules: Vec<SourceModule>,
+    /// Evaluate the code while the module is built, so the snapshot holds
+    /// its state, rather than when the module starts.
+    pub initialize: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) enum SourceKind {
+    Script,
+    Module,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SourceModule {
+    /// The module name QuickJS resolves imports against.
+    pub name: String,
+    pub code: Code,
+    /// Used by the core to map stack traces back to the original sources.
+    pub source_map: Option<sourcemap::SourceMap>,
+}
+
+/// A module's code. The engine's `_apoxy_compile` replaces the text with
+/// QuickJS bytecode, see `crates/core/src/source.rs`.
+#[derive(Debug, Serialize)]
+pub(crate) enum Code {
+    Text(String),
+}
+
+impl Source {
+    /// Loads `entry` and the modules it imports. Each module's source map is
+    /// found through its `sourceMappingURL` comment; `source_map` overrides
+    /// the one of the entry point.
+    pub fn load(entry: &Path, source_map: Option<&Path>) -> Result<Self> {
+        let code = read(entry)?;
+        let program = parse(entry, &code)?;
+        let entry_map = match source_map {
+            Some(path) => Some(sourcemap::SourceMap::load(path)?),
+            None => sourcemap::SourceMap::find(entry, &code)?,
+        };
+        if imports(&program).is_none() {
+            return Ok(Source {
+                kind: SourceKind::Script,
+                modules: vec![SourceModule {
+                    name: "script.js".to_string(),
+                    code: Code::Text(code),
+                    source_map: entry_map,
+                }],
+                initialize: true,
+            });
+        }
+
+        let name = entry
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|| "index.js".to_string());
+        let mut graph = ModuleGraph::default();
+        graph.visit(name, entry.to_path_buf(), code, program)?;
+        if let Some(module) = graph.modules.last_mut() {
+            module.source_map = entry_map;
+        }
+
+        Ok(Source {
+            kind: SourceKind::Module,
+            modules: graph.modules,
+            initialize: true,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec(self)?)
+    }
+}
+
+#[derive(Default)]
+struct ModuleGraph {
+    modules: Vec<SourceModule>,
+    /// Files being walked, to catch import cycles.
+    visiting: HashSet<PathBuf>,
+    /// The name each file was first imported as, and whether it has a default
+    /// export, by canonical path.
+    visited: HashMap<PathBuf, (String, bool)>,
+    /// Every name in `modules`, including aliases.
+    names: HashSet<String>,
+}
+
+impl ModuleGraph {
+    /// Post-order walk, which matches the order ES modules are evaluated in.
+    /// Modules are keyed by the file they resolve to, so different
+    /// specifiers for the same file share one instance.
+    fn visit(&mut self, name: String, path: PathBuf, code: String, program: Program) -> Result<()> {
+        let path = fs::canonicalize(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        self.visiting.insert(path.clone());
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for specifier in imports(&program).unwrap_or_default() {
+            if !specifier.starts_with("./") && !specifier.starts_with("../") {
+                bail!(
+                    "{}: cannot resolve import {:?}, only relative imports are supported. Bundle dependencies with esbuild first.",
+                    path.display(),
+                    specifier
+                );
+            }
+
+            let dep_name = normalize(&name, &specifier);
+            if self.names.contains(&dep_name) {
+                continue;
+            }
+            let dep_path = fs::canonicalize(resolve(dir, &specifier)?)?;
+            if self.visiting.contains(&dep_path) {
+                bail!(
+                    "{}: circular import of {:?} is not supported",
+                    path.display(),
+                    specifier
+                );
+            }
+            if let Some((target, default)) = self.visited.get(&dep_path) {
+                let code = alias(target, *default);
+                self.push(dep_name, code, None);
+                continue;
+            }
+
+            let dep_code = read(&dep_path)?;
+            let dep_program = parse(&dep_path, &dep_code)?;
+            self.visit(dep_name, dep_path, dep_code, dep_program)?;
+        }
+
+        self.visiting.remove(&path);
+        self.visited
+            .insert(path.clone(), (name.clone(), has_default_export(&program)));
+        let source_map = sourcemap::SourceMap::find(&path, &code)?;
+        self.push(name, code, source_map);
+        Ok(())
+    }
+
+    fn push(&mut self, name: String, code: String, source_map: Option<sourcemap::SourceMap>) {
+        self.names.insert(name.clone());
+        self.modules.push(SourceModule {
+            name,
+            code: Code::Text(code),
+            source_map,
+        });
+    }
+}
+
+/// A module that re-exports `target`, for another specifier of the same file.
+/// QuickJS keys modules by name, so this keeps a single instance of the file.
+fn alias(target: &str, default: bool) -> String {
+    let mut code = format!("export * from {:?};", target);
+    if default {
+        code.push_str(&format!(" export {{ default }} from {:?};", target));
+    }
+    code
+}
+
+fn has_default_export(program: &Program) -> bool {
+    let Program::Module(module) = program else {
+        return false;
+    };
+    module.body.iter().any(|item| match item {
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(_))
+        | ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(_)) => true,
+        ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(export)) => {
+            export.specifiers.iter().any(|x| match x {
+                ExportSpecifier::Named(named) => {
+                    let exported = named.exported.as_ref().unwrap_or(&named.orig);
+                    matches!(exported, ModuleExportName::Ident(x) if &*x.sym == "default")
+                        || matches!(exported, ModuleExportName::Str(x) if &*x.value == "default")
+                }
+                ExportSpecifier::Default(_) => true,
+                ExportSpecifier::Namespace(_) => false,
+            })
+        }
+        _ => false,
+    })
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// A syntax error, or syntax the embedded QuickJS doesn't support.
+struct Diagnostic {
+    span: Span,
+    message: String,
+}
+
+impl From<swc_ecma_parser::error::Error> for Diagnostic {
+    fn from(e: swc_ecma_parser::error::Error) -> Self {
+        Diagnostic {
+            span: e.span(),
+            message: e.kind().msg().to_string(),
+        }
+    }
+}
+
+/// Parses `code`, failing with a code frame for every syntax error and every
+/// use of syntax QuickJS can't evaluate.
+fn parse(path: &Path, code: &str) -> Result<Program> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Real(path.to_path_buf()), code.to_string());
+    let lexer = Lexer::new(
+        Syntax::Es(EsConfig::default()),
+        EsVersion::Es2020,
+        StringInput::from(&*fm),
+        None,
+    );
+    let mut parser = Parser::new_from(Capturing::new(lexer));
+
+    let program = parser.parse_program();
+    let mut diagnostics: Vec<Diagnostic> = parser
+        .take_errors()
+        .into_iter()
+        .map(Diagnostic::from)
+        .collect();
+    let program = match program {
+        Ok(program) => Some(program),
+        Err(e) => {
+            diagnostics.push(e.into());
+            None
+        }
+    };
+    diagnostics.extend(unsupported(&parser.input().take()));
+
+    match program {
+        Some(program) if diagnostics.is_empty() => Ok(program),
+        _ => {
+            diagnostics.sort_by_key(|x| x.span.lo);
+            let frames: Vec<String> = diagnostics
+                .iter()
+                .map(|x| code_frame(&cm, path, x))
+                .collect();
+            bail!(
+                "{} cannot be compiled:\n\n{}",
+                path.display(),
+                frames.join("\n")
+            )
+        }
+    }
+}
+
+/// Finds syntax newer than the QuickJS release the core embeds, which the
+/// parser accepts but evaluation would reject.
+fn unsupported(tokens: &[TokenAndSpan]) -> Vec<Diagnostic> {
+    let token = |i: usize| tokens.get(i).map(|x| &x.token);
+    tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| {
+            let message = match &x.token {
+                Token::Word(Word::Ident(IdentLike::Known(KnownIdent::Static)))
+                    if token(i + 1) == Some(&Token::LBrace) =>
+                {
+                    "class static blocks (ES2022) are not supported"
+                }
+                // `this.#field in object` reads the field, which is fine.
+                Token::Hash
+                    if token(i + 2) == Some(&Token::Word(Word::Keyword(Keyword::In)))
+                        && (i == 0 || token(i - 1) != Some(&Token::Dot)) =>
+                {
+                    "`#field in object` checks (ES2022) are not supported"
+                }
+                Token::Regex(_, flags) if flags.contains('d') => {
+                    "the regular expression `d` flag (ES2022) is not supported"
+                }
+                Token::Regex(_, flags) if flags.contains('v') => {
+                    "the regular expression `v` flag (ES2024) is not supported"
+                }
+                _ => return None,
+            };
+            Some(Diagnostic {
+                span: x.span,
+                message: message.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn code_frame(cm: &SourceMap, path: &Path, diagnostic: &Diagnostic) -> String {
+    let start = cm.lookup_char_pos(diagnostic.span.lo);
+    let end = cm.lookup_char_pos(diagnostic.span.hi);
+    let line = start.file.get_line(start.line - 1).unwrap_or_default();
+
+    let indent: String = line
+        .chars()
+        .take(start.col.0)
+        .map(|x| if x == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = if end.line == start.line {
+        end.col.0.saturating_sub(start.col.0)
+    } else {
+        line.chars().count().saturating_sub(start.col.0)
+    };
+
+    let number = start.line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+        diagnostic.message,
+        gutter,
+        path.display(),
+        start.line,
+        start.col_display + 1,
+        gutter,
+        number,
+        line.trim_end(),
+        gutter,
+        indent,
+        "^".repeat(width.max(1))
+    )
+}
+
+/// Returns the specifiers of every static import and re-export, or `None`
+/// if the program is a plain script.
+fn imports(program: &Program) -> Option<Vec<String>> {
+    let Program::Module(module) = program else {
+        return None;
+    };
+
+    let decls: Vec<&ModuleDecl> = module
+        .body
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::ModuleDecl(decl) => Some(decl),
+            ModuleItem::Stmt(_) => None,
+        })
+        .collect();
+    if decls.is_empty() {
+        return None;
+    }
+
+    Some(
+        decls
+            .into_iter()
+            .filter_map(|decl| match decl {
+                ModuleDecl::Import(import) => Some(import.src.value.to_string()),
+                ModuleDecl::ExportAll(export) => Some(export.src.value.to_string()),
+                ModuleDecl::ExportNamed(export) => {
+                    export.src.as_ref().map(|src| src.value.to_string())
+                }
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// Resolves `specifier` relative to the module `base` the same way QuickJS'
+/// default module name normalizer does.
+fn normalize(base: &str, specifier: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in specifier.split('/') {
+        match part {
+            "." | "" => {}
+            ".." => match parts.last() {
+                Some(&last) if last != ".." => {
+                    parts.pop();
+                }
+                _ => parts.push(".."),
+            },
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn resolve(dir: &Path, specifier: &str) -> Result<PathBuf> {
+    let path = dir.join(specifier);
+    // Appended rather than set with `with_extension`, which would turn
+    // `./lib.v2` into `lib.js`.
+    let with_suffix = |suffix: &str| {
+        let mut path = path.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    let candidates = [
+        path.clone(),
+        with_suffix(".js"),
+        with_suffix(".mjs"),
+        path.join("index.js"),
+    ];
+    candidates
+        .into_iter()
+        .find(|x| x.is_file())
+        .ok_or_else(|| anyhow!("Cannot find module {:?} in {}", specifier, dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(files: &[(&str, &str)]) -> Result<Source> {
+        let dir = tempfile::tempdir()?;
+        for (name, code) in files {
+            fs::write(dir.path().join(name), code)?;
+        }
+        Source::load(&dir.path().join(files[0].0), None)
+    }
+
+    fn modules(source: &Source) -> Vec<(&str, &str)> {
+        source
+            .modules
+            .iter()
+            .map(|x| {
+                let Code::Text(code) = &x.code;
+                (x.name.as_str(), code.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evaluates_each_file_once() {
+        let source = load(&[
+            (
+                "index.js",
+                "import { count } from './util';\nimport bump from './util.js';\nbump();",
+            ),
+            (
+                "util.js",
+                "export let count = 0;\nexport default function bump() { count++; }",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            modules(&source),
+            [
+                (
+                    "util",
+                    "export let count = 0;\nexport default function bump() { count++; }"
+                ),
+                (
+                    "util.js",
+                    r#"export * from "util"; export { default } from "util";"#
+                ),
+                (
+                    "index.js",
+                    "import { count } from './util';\nimport bump from './util.js';\nbump();"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn aliases_leave_out_a_missing_default_export() {
+        let source = load(&[
+            ("index.js", "import './a.js';\nimport './a';"),
+            ("a.js", "export const a = 1;"),
+        ])
+        .unwrap();
+        assert_eq!(modules(&source)[1], ("a", r#"export * from "a.js";"#));
+        assert_eq!(modules(&source).len(), 3);
+    }
+
+    #[test]
+    fn appends_extensions_to_dotted_names() {
+        let source = load(&[
+            ("index.js", "import { v } from './lib.v2';"),
+            ("lib.v2.js", "export const v = 2;"),
+            ("lib.js", "export const v = 1;"),
+        ])
+        .unwrap();
+        assert_eq!(modules(&source)[0], ("lib.v2", "export const v = 2;"));
+    }
+
+    #[test]
+    fn rejects_circular_imports() {
+        let err = load(&[
+            ("index.js", "import './a.js';"),
+            ("a.js", "import './b.js';"),
+            ("b.js", "import './a';"),
+        ])
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("circular import"), "{}", err);
+    }
+
+    #[test]
+    fn allows_private_field_reads_before_in() {
+        let code = "class A { #a = 1; has(o) { return this.#a in o || this?.#a in o; } }";
+        parse(Path::new("a.js"), code).unwrap();
+    }
+
+    #[test]
+    fn rejects_private_brand_checks() {
+        let code = "class A { #a = 1; static is(o) { return #a in o; } }";
+        let err = parse(Path::new("a.js"), code).err().unwrap();
+        assert!(err.to_string().contains("`#field in object`"), "{}", err);
+    }
+}
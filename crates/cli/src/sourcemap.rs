@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// A source map decoded into the form the core looks positions up in.
+///
+/// This is serialized with MessagePack and must stay in sync with
+/// `crates/core/src/sourcemap.rs`.
+#[derive(Debug, Serialize)]
+pub(crate) struct SourceMap {
+    pub sources: Vec<String>,
+    /// The mappings of each generated line, ordered by column.
+    pub lines: Vec<Vec<Segment>>,
+}
+
+/// A mapping from a generated column to an original position. Every field is
+/// 0-based.
+#[derive(Debug, Serialize)]
+pub(crate) struct Segment {
+    pub column: u32,
+    pub source: u32,
+    pub line: u32,
+    pub source_column: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+    #[serde(default)]
+    source_root: Option<String>,
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    mappings: Option<String>,
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl SourceMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read source map {}", path.display()))?;
+        Self::parse(&json).with_context(|| format!("Invalid source map {}", path.display()))
+    }
+
+    /// Follows the `sourceMappingURL` comment at the end of `code`, which is
+    /// either an inline `data:` URL or a path relative to the file.
+    pub fn find(path: &Path, code: &str) -> Result<Option<Self>> {
+        let Some(url) = code.lines().rev().find_map(|line| {
+            line.trim()
+                .strip_prefix("//# sourceMappingURL=")
+                .or_else(|| line.trim().strip_prefix("//@ sourceMappingURL="))
+        }) else {
+            return Ok(None);
+        };
+
+        if let Some(data) = url.strip_prefix("data:") {
+            let (_, encoded) = data
+                .split_once(";base64,")
+                .ok_or_else(|| anyhow!("{}: inline source maps must be base64", path.display()))?;
+            let json = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .with_context(|| format!("{}: invalid inline source map", path.display()))?;
+            let json = String::from_utf8(json)
+                .with_context(|| format!("{}: invalid inline source map", path.display()))?;
+            return Self::parse(&json)
+                .with_context(|| format!("{}: invalid inline source map", path.display()))
+                .map(Some);
+        }
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Self::load(&dir.join(url)).map(Some)
+    }
+
+    fn parse(json: &str) -> Result<Self> {
+        let raw: RawSourceMap = serde_json::from_str(json)?;
+        if raw.version != 3 {
+            bail!("unsupported source map version {}", raw.version);
+        }
+        let Some(mappings) = raw.mappings else {
+            bail!("index source maps are not supported");
+        };
+
+        let root = raw.source_root.unwrap_or_default();
+        let sources = raw
+            .sources
+            .into_iter()
+            .map(|source| match root.as_str() {
+                "" => source.unwrap_or_default(),
+                root => format!(
+                    "{}/{}",
+                    root.trim_end_matches('/'),
+                    source.unwrap_or_default()
+                ),
+            })
+            .collect();
+
+        Ok(Self {
+            sources,
+            lines: decode(&mappings)?,
+        })
+    }
+}
+
+/// Decodes the `mappings` field. Generated columns restart on every line,
+/// the other fields are relative to the previous segment in the whole map.
+fn decode(mappings: &str) -> Result<Vec<Vec<Segment>>> {
+    let (mut source, mut line, mut source_column) = (0i64, 0i64, 0i64);
+    mappings
+        .split(';')
+        .map(|group| {
+            let mut column = 0i64;
+            let mut segments = Vec::new();
+            for segment in group.split(',').filter(|x| !x.is_empty()) {
+                let fields = decode_vlq(segment)?;
+                column += fields[0];
+                // Segments with a single field map to nothing.
+                if fields.len() < 4 {
+                    continue;
+                }
+                source += fields[1];
+                line += fields[2];
+                source_column += fields[3];
+                segments.push(Segment {
+                    column: u32::try_from(column)?,
+                    source: u32::try_from(source)?,
+                    line: u32::try_from(line)?,
+                    source_column: u32::try_from(source_column)?,
+                });
+            }
+            Ok(segments)
+        })
+        .collect()
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>> {
+    let mut values = Vec::new();
+    let (mut value, mut shift) = (0i64, 0);
+    for c in segment.bytes() {
+        let digit = BASE64
+            .iter()
+            .position(|&x| x == c)
+            .ok_or_else(|| anyhow!("invalid character {:?} in mappings", c as char))?
+            as i64;
+        value += (digit & 31) << shift;
+        if digit & 32 != 0 {
+            shift += 5;
+            continue;
+        }
+        values.push(if value & 1 == 1 {
+            -(value >> 1)
+        } else {
+            value >> 1
+        });
+        value = 0;
+        shift = 0;
+    }
+    if values.is_empty() {
+        bail!("empty segment in mappings");
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(lines: &[Vec<Segment>]) -> Vec<Vec<(u32, u32, u32, u32)>> {
+        lines
+            .iter()
+            .map(|segments| {
+                segments
+                    .iter()
+                    .map(|x| (x.column, x.source, x.line, x.source_column))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_vlq_values() {
+        assert_eq!(decode_vlq("A").unwrap(), [0]);
+        assert_eq!(decode_vlq("C").unwrap(), [1]);
+        assert_eq!(decode_vlq("D").unwrap(), [-1]);
+        assert_eq!(decode_vlq("e").unwrap(), [15]);
+        assert_eq!(decode_vlq("gB").unwrap(), [16]);
+        assert_eq!(decode_vlq("hB").unwrap(), [-16]);
+        assert_eq!(decode_vlq("2H").unwrap(), [123]);
+        assert_eq!(decode_vlq("x+B").unwrap(), [-1000]);
+        assert_eq!(decode_vlq("gqjG").unwrap(), [100000]);
+        assert_eq!(decode_vlq("AAgBD").unwrap(), [0, 0, 16, -1]);
+    }
+
+    #[test]
+    fn rejects_invalid_mappings() {
+        assert!(decode_vlq("A!").is_err());
+        assert!(decode_vlq("").is_err());
+    }
+
+    #[test]
+    fn decodes_mappings_relative_to_the_previous_segment() {
+        // Columns restart on each line, the other fields carry over lines,
+        // including empty ones, and go backwards through negative deltas.
+        let lines = decode("AAAA,SAAS,IAAI;EACX;;ACuCF,w+BDrCiB;I").unwrap();
+        assert_eq!(
+            positions(&lines),
+            [
+                vec![(0, 0, 0, 0), (9, 0, 0, 9), (13, 0, 0, 13)],
+                vec![(2, 0, 1, 2)],
+                vec![],
+                vec![(0, 1, 40, 0), (1000, 0, 3, 17)],
+                // A segment with only a column maps to nothing.
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_an_esbuild_style_source_map() {
+        // Laid out the way esbuild writes maps, for
+        // `export const greet = (name: string) => "hi " + name;` compiled to
+        // `var greet = (name) => "hi " + name;` below a banner line.
+        let map = SourceMap::parse(
+            r#"{
+                "version": 3,
+                "sources": ["../src/index.ts"],
+                "sourcesContent": ["export const greet = (name: string) => \"hi \" + name;\n"],
+                "mappings": ";AAAO,IAAM,QAAQ,CAAC,SAAiB,QAAQ;",
+                "names": []
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(map.sources, ["../src/index.ts"]);
+        assert_eq!(
+            positions(&map.lines),
+            [
+                vec![],
+                vec![
+                    (0, 0, 0, 7),
+                    (4, 0, 0, 13),
+                    (12, 0, 0, 21),
+                    (13, 0, 0, 22),
+                    (22, 0, 0, 39),
+                    (30, 0, 0, 47),
+                ],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixes_sources_with_the_source_root() {
+        let map = SourceMap::parse(
+            r#"{"version": 3, "sourceRoot": "src/", "sources": ["a.ts", null], "mappings": ""}"#,
+        )
+        .unwrap();
+        assert_eq!(map.sources, ["src/a.ts", "src/"]);
+    }
+
+    #[test]
+    fn rejects_other_versions_and_index_maps() {
+        assert!(SourceMap::parse(r#"{"version": 2, "mappings": ""}"#).is_err());
+        assert!(SourceMap::parse(r#"{"version": 3, "sections": []}"#).is_err());
+    }
+}
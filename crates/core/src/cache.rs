@@ -0,0 +1,78 @@
+use extism_pdk::*;
+
+/// Prefixed to the var names of entries, so that they don't clash with vars
+/// the module sets itself.
+const PREFIX: &str = "apoxy.cache:";
+/// Holds the bytes used by all entries, as a little-endian u64. Vars outlive
+/// the instance's memory, so the total is kept with them.
+const USED: &str = "apoxy.cache";
+
+/// Defaults for the limits, which stay below the 1 MiB the Extism runtime
+/// allows for all vars by default. Going over that traps the module instead
+/// of raising an error it could catch.
+const MAX_BYTES: usize = 512 * 1024;
+const MAX_VALUE_BYTES: usize = 64 * 1024;
+
+fn limit(key: &str, default: usize) -> Result<usize, Error> {
+    match config::get(key)? {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| Error::msg(format!("{} is not a number of bytes", key))),
+        None => Ok(default),
+    }
+}
+
+fn used() -> Result<usize, Error> {
+    let bytes = var::get::<Vec<u8>>(USED)?.unwrap_or_default();
+    let total = bytes.try_into().map(u64::from_le_bytes).unwrap_or_default();
+    Ok(total as usize)
+}
+
+fn set_used(total: usize) -> Result<(), Error> {
+    var::set(USED, (total as u64).to_le_bytes().to_vec())
+}
+
+/// What an entry counts against the limit.
+fn size(key: &str, value: &[u8]) -> usize {
+    key.len() + value.len()
+}
+
+pub fn get(key: &str) -> Result<Option<Vec<u8>>, Error> {
+    var::get(format!("{}{}", PREFIX, key))
+}
+
+pub fn set(key: &str, value: &[u8]) -> Result<(), Error> {
+    let max_value = limit("APOXY_CACHE_MAX_VALUE_BYTES", MAX_VALUE_BYTES)?;
+    if value.len() > max_value {
+        return Err(Error::msg(format!(
+            "{:?} is {} bytes, more than the {} allowed per value",
+            key,
+            value.len(),
+            max_value
+        )));
+    }
+
+    let old = get(key)?.map_or(0, |x| size(key, &x));
+    let total = used()?.saturating_sub(old) + size(key, value);
+    let max = limit("APOXY_CACHE_MAX_BYTES", MAX_BYTES)?;
+    if total > max {
+        return Err(Error::msg(format!(
+            "storing {:?} would use {} bytes, more than the {} allowed",
+            key, total, max
+        )));
+    }
+
+    var::set(format!("{}{}", PREFIX, key), value.to_vec())?;
+    set_used(total)
+}
+
+/// Removes an entry, returning whether there was one.
+pub fn delete(key: &str) -> Result<bool, Error> {
+    let Some(old) = get(key)? else {
+        return Ok(false);
+    };
+    var::remove(format!("{}{}", PREFIX, key))?;
+    set_used(used()?.saturating_sub(size(key, &old)))?;
+    Ok(true)
+}
@@ -1,27 +1,13 @@
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use extism_pdk::*;
-use rmp_serde::{Deserializer, Serializer};
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct FetchRequest {
-    pub url: String,
-    pub method: String,
-    pub headers: HashMap<String, String>,
-}
+use js_abi::FetchResponse;
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
-struct FetchResponse {
-    status: u16,
-    headers: HashMap<String, String>,
-    body_offset: u64,
-    error: Option<String>,
-}
+pub use js_abi::{FetchRequest, Headers};
 
 pub struct HttpResponse {
     status: u16,
-    headers: HashMap<String, String>,
+    headers: Headers,
     body: Memory,
 }
 
@@ -30,7 +16,7 @@ impl HttpResponse {
         self.status
     }
 
-    pub fn headers(&self) -> &HashMap<String, String> {
+    pub fn headers(&self) -> &Headers {
         &self.headers
     }
 
@@ -41,13 +27,17 @@ impl HttpResponse {
 
 #[link(wasm_import_module = "extism:host/user")]
 extern "C" {
-    fn _apoxy_fetch(req: u64, body: u64) -> u64;
+    fn _apoxy_fetch_start(req: u64, body: u64) -> u64;
+    fn _apoxy_fetch_wait() -> u64;
 }
 
-pub fn request<T: ToMemory>(req: &FetchRequest, body: Option<T>) -> Result<HttpResponse, Error> {
-    let mut fetch_msg = Vec::new();
-    req.serialize(&mut Serializer::new(&mut fetch_msg))?;
-    let fetch_mem = Memory::from_bytes(&fetch_msg)?;
+/// Requests started with `start` that `wait` has not returned yet.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Hands the request to the host and returns a handle for it without waiting
+/// for the response.
+pub fn start<T: ToMemory>(req: &FetchRequest, body: Option<T>) -> Result<u64, Error> {
+    let fetch_mem = Memory::from_bytes(js_abi::encode(req)?)?;
 
     let body = match body {
         Some(b) => Some(b.to_memory()?),
@@ -55,29 +45,51 @@ pub fn request<T: ToMemory>(req: &FetchRequest, body: Option<T>) -> Result<HttpR
     };
     let data = body.as_ref().map(|x| x.offset()).unwrap_or(0);
 
-    let offs = unsafe { _apoxy_fetch(fetch_mem.offset(), data) };
+    let handle = unsafe { _apoxy_fetch_start(fetch_mem.offset(), data) };
+    debug!("fetch handle: {}", handle);
+    if handle == js_abi::NONE {
+        return Err(Error::msg("fetch failed"));
+    }
+    IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+    Ok(handle)
+}
+
+/// Forgets requests left in flight by an earlier invocation, which the host
+/// has dropped along with the rest of its per-request state.
+pub fn reset() {
+    IN_FLIGHT.store(0, Ordering::SeqCst);
+}
+
+pub fn in_flight() -> bool {
+    IN_FLIGHT.load(Ordering::SeqCst) > 0
+}
+
+/// Blocks until the host completes one of the in-flight requests and returns
+/// its handle along with the response.
+pub fn wait() -> Result<(u64, Result<HttpResponse, Error>), Error> {
+    let offs = unsafe { _apoxy_fetch_wait() };
     debug!("fetch response offset: {}", offs);
-    match offs {
-        o if o <= 0 => return Err(Error::msg("fetch failed")),
-        _ => (),
+    if offs == js_abi::NONE {
+        return Err(Error::msg("no fetch in flight"));
     }
+    IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+
     let len = unsafe { extism::length_unsafe(offs) };
     let resp_mem = Memory(MemoryHandle {
         offset: offs,
         length: len,
     });
     let resp_bytes = resp_mem.to_vec();
-    let mut deserialize = Deserializer::from_read_ref(&resp_bytes);
-    let resp = FetchResponse::deserialize(&mut deserialize)?;
+    let resp: FetchResponse = js_abi::decode(&resp_bytes)?;
     let body_len = match resp.body_offset {
-        0 => 0,
+        js_abi::NONE => 0,
         _ => unsafe { extism::length_unsafe(resp.body_offset) },
     };
 
     debug!("response: {:?}", resp);
 
-    match resp.error {
-        Some(e) => return Err(Error::msg(e)),
+    let result = match resp.error {
+        Some(e) => Err(Error::msg(e)),
         None => Ok(HttpResponse {
             status: resp.status,
             headers: resp.headers,
@@ -86,5 +98,6 @@ pub fn request<T: ToMemory>(req: &FetchRequest, body: Option<T>) -> Result<HttpR
                 length: body_len,
             }),
         }),
-    }
+    };
+    Ok((resp.handle, result))
 }
@@ -1,13 +1,62 @@
-use std::{borrow::Cow, collections::HashMap, str::from_utf8};
-
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    str::from_utf8,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Mutex,
+    time::Instant,
+};
+
+use crate::cache;
 use crate::fetch::*;
+use crate::kv;
 use anyhow::{anyhow, Context};
 use chrono::{SecondsFormat, Utc};
 use extism_pdk::*;
-use javy::json;
+use javy::messagepack;
+use once_cell::sync::OnceCell;
 use quickjs_wasm_rs::{JSContextRef, JSError, JSValue, JSValueRef};
 
+/// Set by `__apoxy_handler_error` when a handler throws, see `_apoxy_start`.
+static HANDLER_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn take_handler_error() -> Option<String> {
+    HANDLER_ERROR.lock().unwrap().take()
+}
+
+/// Set while user code runs inside `wizer.initialize`, where calling a host
+/// function traps.
+static INITIALIZING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_initializing(initializing: bool) {
+    INITIALIZING.store(initializing, Ordering::SeqCst);
+}
+
+fn runtime_only(api: &str) -> anyhow::Result<()> {
+    if INITIALIZING.load(Ordering::SeqCst) {
+        return Err(anyhow!(
+            "[core] {} is not available while the module is initialized",
+            api
+        ));
+    }
+    Ok(())
+}
+
+/// Logs through the host, or to stderr during initialization. Stack traces
+/// in the statement are mapped to the original sources.
+fn console_log(level: LogLevel, stmt: &str) {
+    let stmt = crate::map_stack(stmt);
+    if INITIALIZING.load(Ordering::SeqCst) {
+        eprintln!("{}", stmt);
+    } else {
+        log!(level, "{}", stmt);
+    }
+}
+
 static PRELUDE: &[u8] = include_bytes!("prelude/dist/index.js"); // if this panics, run `make` from the root
+/// The file name prelude frames carry in stack traces. It can't collide with a
+/// user module, so their source maps never apply to it.
+pub(crate) const PRELUDE_NAME: &str = "apoxy:prelude";
 
 pub fn inject_globals(context: &JSContextRef) -> anyhow::Result<()> {
     let module = build_module_object(context)?;
@@ -15,14 +64,24 @@ pub fn inject_globals(context: &JSContextRef) -> anyhow::Result<()> {
     let decoder = build_decoder(context)?;
     let encoder = build_encoder(context)?;
     let clock = build_clock(context)?;
+    let monotonic_clock = build_monotonic_clock(context)?;
 
     let apoxy = build_apoxy_object(context)?;
     let fetch = build_fetch_object(context)?;
-    let apoxy_req_body = build_apoxy_req_body_object(context)?;
+    let apoxy_req_body_read = build_apoxy_body_read_object(context, _apoxy_req_body_read)?;
     let apoxy_req_send = build_apoxy_req_send_object(context)?;
-    let apoxy_resp_body = build_apoxy_resp_body_object(context)?;
+    let apoxy_req_set = build_apoxy_req_set_object(context)?;
+    let apoxy_resp_body_read = build_apoxy_body_read_object(context, _apoxy_resp_body_read)?;
     let apoxy_resp_send = build_apoxy_resp_send_object(context)?;
     let apoxy_send_downstream = build_apoxy_send_downstream_object(context)?;
+    let apoxy_resp_stream = build_apoxy_resp_stream_object(context)?;
+    let apoxy_resp_write = build_apoxy_resp_write_object(context)?;
+    let apoxy_resp_close = build_apoxy_resp_close_object(context)?;
+    let apoxy_handler_error = build_apoxy_handler_error_object(context)?;
+    let apoxy_map_stack = build_apoxy_map_stack_object(context)?;
+    let apoxy_log = build_apoxy_log_object(context)?;
+    let apoxy_kv = build_apoxy_kv_object(context)?;
+    let apoxy_cache = build_apoxy_cache_object(context)?;
 
     let global = context.global_object()?;
     global.set_property("console", console)?;
@@ -30,22 +89,32 @@ pub fn inject_globals(context: &JSContextRef) -> anyhow::Result<()> {
     global.set_property("__decodeUtf8BufferToString", decoder)?;
     global.set_property("__encodeStringToUtf8Buffer", encoder)?;
     global.set_property("__getTime", clock)?;
+    global.set_property("__getMonotonicTime", monotonic_clock)?;
 
     global.set_property("Apoxy", apoxy)?;
     global.set_property("__fetch", fetch)?;
-    global.set_property("__apoxy_req_body", apoxy_req_body)?;
+    global.set_property("__apoxy_req_body_read", apoxy_req_body_read)?;
     global.set_property("__apoxy_req_send", apoxy_req_send)?;
-    global.set_property("__apoxy_resp_body", apoxy_resp_body)?;
+    global.set_property("__apoxy_req_set", apoxy_req_set)?;
+    global.set_property("__apoxy_resp_body_read", apoxy_resp_body_read)?;
     global.set_property("__apoxy_resp_send", apoxy_resp_send)?;
     global.set_property("__apoxy_send_downstream", apoxy_send_downstream)?;
+    global.set_property("__apoxy_resp_stream", apoxy_resp_stream)?;
+    global.set_property("__apoxy_resp_write", apoxy_resp_write)?;
+    global.set_property("__apoxy_resp_close", apoxy_resp_close)?;
+    global.set_property("__apoxy_handler_error", apoxy_handler_error)?;
+    global.set_property("__apoxy_map_stack", apoxy_map_stack)?;
+    global.set_property("__apoxy_log", apoxy_log)?;
+    global.set_property("__apoxy_kv", apoxy_kv)?;
+    global.set_property("__apoxy_cache", apoxy_cache)?;
 
     context.eval_global(
-        "script.js",
+        PRELUDE_NAME,
         "globalThis.module = {}; globalThis.module.exports = {}",
     )?;
     // need a *global* var for polyfills to work
-    context.eval_global("script.js", "global = globalThis")?;
-    context.eval_global("script.js", from_utf8(PRELUDE)?)?;
+    context.eval_global(PRELUDE_NAME, "global = globalThis")?;
+    context.eval_global(PRELUDE_NAME, from_utf8(PRELUDE)?)?;
 
     Ok(())
 }
@@ -68,48 +137,137 @@ fn build_apoxy_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
     let apoxy_env = context.object_value()?;
     let apoxy_env_get = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
-            let key = args.first().unwrap().as_str()?;
+            runtime_only("Apoxy.env")?;
+            let key = key_arg(args)?;
             debug!("[core/env.get] key: {}", key);
-            let value = config::get(&key).unwrap_or_default().unwrap();
-            Ok(JSValue::String(value))
+            match config::get(key)? {
+                Some(value) => Ok(JSValue::String(value)),
+                None => Ok(JSValue::Null),
+            }
+        },
+    )?;
+    let apoxy_env_keys = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]| {
+            runtime_only("Apoxy.env")?;
+            let offs = unsafe { _apoxy_env_keys() };
+            let keys: Vec<String> = js_abi::decode(&take_block(offs))?;
+            Ok(JSValue::Array(
+                keys.into_iter().map(JSValue::String).collect(),
+            ))
         },
     )?;
     apoxy_env.set_property("get", apoxy_env_get)?;
+    apoxy_env.set_property("keys", apoxy_env_keys)?;
+
+    let apoxy_secrets = context.object_value()?;
+    let apoxy_secrets_get = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.secrets")?;
+            let key_mem = Memory::from_bytes(key_arg(args)?)?;
+            let offs = unsafe { _apoxy_secret_get(key_mem.offset()) };
+            key_mem.free();
+            if offs == js_abi::NONE {
+                return Ok(JSValue::Null);
+            }
+            Ok(JSValue::String(String::from_utf8(take_block(offs))?))
+        },
+    )?;
+    apoxy_secrets.set_property("get", apoxy_secrets_get)?;
 
     apoxy_object.set_property("serve", apoxy_serve)?;
     apoxy_object.set_property("env", apoxy_env)?;
+    apoxy_object.set_property("secrets", apoxy_secrets)?;
 
     Ok(apoxy_object)
 }
 
+fn key_arg<'a>(args: &'a [JSValueRef]) -> anyhow::Result<&'a str> {
+    args.first()
+        .ok_or_else(|| anyhow!("[core] Expected a key"))?
+        .as_str()
+}
+
+/// Copies out and frees a block the host returned.
+fn take_block(offs: u64) -> Vec<u8> {
+    let len = unsafe { extism::length_unsafe(offs) };
+    let mem = Memory(MemoryHandle {
+        offset: offs,
+        length: len,
+    });
+    let bytes = mem.to_vec();
+    mem.free();
+    bytes
+}
+
 #[link(wasm_import_module = "extism:host/user")]
 extern "C" {
-    pub fn _apoxy_req_body(offs: u64) -> u64;
+    /// Returns the next chunk of at most `max` bytes, or 0 at the end.
+    pub fn _apoxy_req_body_read(max: u64) -> u64;
     pub fn _apoxy_req_send(req_offs: u64, body_offs: u64) -> u64;
-    pub fn _apoxy_resp_body(offs: u64) -> u64;
+    /// Replaces the request the proxy forwards after the request phase.
+    pub fn _apoxy_req_set(req_offs: u64, body_offs: u64) -> u64;
+    /// Returns the next chunk of at most `max` bytes, or 0 at the end.
+    pub fn _apoxy_resp_body_read(max: u64) -> u64;
     pub fn _apoxy_resp_send(resp_offs: u64, body_offs: u64) -> u64;
     pub fn _apoxy_send_downstream(resp_offs: u64, body_offs: u64) -> u64;
+    /// Sends a response head; the body follows through `_apoxy_resp_write`.
+    pub fn _apoxy_resp_stream(resp_offs: u64, downstream: u64) -> u64;
+    pub fn _apoxy_resp_write(chunk_offs: u64) -> u64;
+    pub fn _apoxy_resp_close() -> u64;
+    /// Emits a structured log record, see `js_abi::LogRecord`.
+    pub fn _apoxy_log(record_offs: u64) -> u64;
+    /// Returns the config keys, as a list of strings.
+    pub fn _apoxy_env_keys() -> u64;
+    /// Returns the secret named by the key, or 0 if there is none.
+    pub fn _apoxy_secret_get(key_offs: u64) -> u64;
 }
 
-fn build_apoxy_req_body_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
-    let apoxy_req_body = context.wrap_callback(
-        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
-            let req_bytes = json::transcode_output(*(args.first().unwrap()))?;
-            let mem = Memory::from_bytes(req_bytes)?;
+fn host_result(ret: u64, message: &str) -> JSValue {
+    if ret != js_abi::OK {
+        return JSValue::from_hashmap(HashMap::from([
+            ("error", JSValue::Bool(true)),
+            ("message", JSValue::String(message.to_string())),
+        ]));
+    }
+    JSValue::from_hashmap(HashMap::from([("error", JSValue::Bool(false))]))
+}
 
-            let offs = unsafe { _apoxy_req_body(mem.offset()) };
+fn build_apoxy_body_read_object(
+    context: &JSContextRef,
+    read: unsafe extern "C" fn(u64) -> u64,
+) -> anyhow::Result<JSValueRef> {
+    let apoxy_body_read = context.wrap_callback(
+        move |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let max = match JSValue::try_from(*(args.first().unwrap()))? {
+                JSValue::Int(max) => max as u64,
+                JSValue::Float(max) => max as u64,
+                _ => return Err(anyhow!("[core] Invalid chunk size")),
+            };
+
+            let offs = unsafe { read(max) };
+            if offs == js_abi::NONE {
+                return Ok(JSValue::from_hashmap(HashMap::from([
+                    ("error", JSValue::Bool(false)),
+                    ("bytes", JSValue::Null),
+                ])));
+            }
             let len = unsafe { extism::length_unsafe(offs) };
             let mem = Memory(MemoryHandle {
                 offset: offs,
                 length: len,
             });
-
-            let result = HashMap::from([("bytes", JSValue::ArrayBuffer(mem.to_vec()))]);
-            Ok(JSValue::from_hashmap(result))
+            let bytes = mem.to_vec();
+            // Chunks are copied into the VM, so they don't pile up in memory.
+            mem.free();
+
+            Ok(JSValue::from_hashmap(HashMap::from([
+                ("error", JSValue::Bool(false)),
+                ("bytes", JSValue::ArrayBuffer(bytes)),
+            ])))
         },
     )?;
 
-    Ok(apoxy_req_body)
+    Ok(apoxy_body_read)
 }
 
 fn build_apoxy_req_send_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
@@ -117,7 +275,7 @@ fn build_apoxy_req_send_object(context: &JSContextRef) -> anyhow::Result<JSValue
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
             let this = args.first().unwrap();
 
-            let req_bytes = json::transcode_output(*(args.get(1).unwrap()))?;
+            let req_bytes = messagepack::transcode_output(*(args.get(1).unwrap()))?;
             let req_mem = Memory::from_bytes(req_bytes)?;
 
             let body_bytes = args.get(2).unwrap().as_bytes()?;
@@ -130,7 +288,7 @@ fn build_apoxy_req_send_object(context: &JSContextRef) -> anyhow::Result<JSValue
                 length: len,
             });
 
-            let response = json::transcode_input(_ctx, mem.to_vec().as_slice()).unwrap();
+            let response = messagepack::transcode_input(_ctx, mem.to_vec().as_slice()).unwrap();
             this.set_property("_abi_response", response).unwrap();
 
             Ok(JSValue::from_hashmap(HashMap::from([(
@@ -143,38 +301,34 @@ fn build_apoxy_req_send_object(context: &JSContextRef) -> anyhow::Result<JSValue
     Ok(apoxy_req_send)
 }
 
-fn build_apoxy_resp_body_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
-    let apoxy_resp_body = context.wrap_callback(
+fn build_apoxy_req_set_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_req_set = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
-            let resp_bytes = json::transcode_output(*(args.first().unwrap()))?;
-            let mem = Memory::from_bytes(resp_bytes)?;
+            let req_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
+            let req_mem = Memory::from_bytes(req_bytes)?;
 
-            let offs = unsafe { _apoxy_resp_body(mem.offset()) };
-            let len = unsafe { extism::length_unsafe(offs) };
-            let mem = Memory(MemoryHandle {
-                offset: offs,
-                length: len,
-            });
+            let body_bytes = args.get(1).unwrap().as_bytes()?;
+            let body_mem = Memory::from_bytes(body_bytes)?;
 
-            let result = HashMap::from([("bytes", JSValue::ArrayBuffer(mem.to_vec()))]);
-            Ok(JSValue::from_hashmap(result))
+            let ret = unsafe { _apoxy_req_set(req_mem.offset(), body_mem.offset()) };
+            Ok(host_result(ret, "Failed to replace request"))
         },
     )?;
 
-    Ok(apoxy_resp_body)
+    Ok(apoxy_req_set)
 }
 
 fn build_apoxy_resp_send_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
     let apoxy_resp_send = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
-            let resp_bytes = json::transcode_output(*(args.first().unwrap()))?;
+            let resp_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
             let resp_mem = Memory::from_bytes(resp_bytes)?;
 
             let body_bytes = args.get(1).unwrap().as_bytes()?;
             let body_mem = Memory::from_bytes(body_bytes)?;
 
             let ret = unsafe { _apoxy_resp_send(resp_mem.offset(), body_mem.offset()) };
-            if ret != 0 {
+            if ret != js_abi::OK {
                 return Ok(JSValue::from_hashmap(HashMap::from([
                     ("error", JSValue::Bool(true)),
                     (
@@ -198,7 +352,7 @@ fn build_apoxy_send_downstream_object(context: &JSContextRef) -> anyhow::Result<
     let apoxy_send_downstream = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
             debug!("__apoxy_send_downstream");
-            let resp_bytes = json::transcode_output(*(args.first().unwrap()))?;
+            let resp_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
             let resp_mem = Memory::from_bytes(resp_bytes)?;
 
             let body_bytes = args.get(1).unwrap().as_bytes()?;
@@ -209,7 +363,7 @@ fn build_apoxy_send_downstream_object(context: &JSContextRef) -> anyhow::Result<
                 body_bytes.len()
             );
             let ret = unsafe { _apoxy_send_downstream(resp_mem.offset(), body_mem.offset()) };
-            if ret != 0 {
+            if ret != js_abi::OK {
                 return Ok(JSValue::from_hashmap(HashMap::from([
                     ("error", JSValue::Bool(true)),
                     (
@@ -229,32 +383,243 @@ fn build_apoxy_send_downstream_object(context: &JSContextRef) -> anyhow::Result<
     Ok(apoxy_send_downstream)
 }
 
+fn build_apoxy_resp_stream_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_resp_stream = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let resp_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
+            let resp_mem = Memory::from_bytes(resp_bytes)?;
+            let downstream = args.get(1).unwrap().as_bool()?;
+
+            let ret = unsafe { _apoxy_resp_stream(resp_mem.offset(), u64::from(downstream)) };
+            Ok(host_result(ret, "Failed to send response"))
+        },
+    )?;
+
+    Ok(apoxy_resp_stream)
+}
+
+fn build_apoxy_resp_write_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_resp_write = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let chunk_mem = Memory::from_bytes(args.first().unwrap().as_bytes()?)?;
+
+            let ret = unsafe { _apoxy_resp_write(chunk_mem.offset()) };
+            chunk_mem.free();
+            Ok(host_result(ret, "Failed to write response body"))
+        },
+    )?;
+
+    Ok(apoxy_resp_write)
+}
+
+fn build_apoxy_resp_close_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_resp_close = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]| {
+            let ret = unsafe { _apoxy_resp_close() };
+            Ok(host_result(ret, "Failed to close response body"))
+        },
+    )?;
+
+    Ok(apoxy_resp_close)
+}
+
+fn build_apoxy_handler_error_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_handler_error = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let message = args.first().unwrap().as_str()?;
+            *HANDLER_ERROR.lock().unwrap() = Some(message.to_string());
+            Ok(JSValue::Undefined)
+        },
+    )?;
+
+    Ok(apoxy_handler_error)
+}
+
+fn build_apoxy_map_stack_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_map_stack = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let stack = args.first().unwrap().as_str()?;
+            Ok(JSValue::String(crate::map_stack(stack)))
+        },
+    )?;
+
+    Ok(apoxy_map_stack)
+}
+
+fn build_apoxy_log_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let apoxy_log = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let record_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
+            if INITIALIZING.load(Ordering::SeqCst) {
+                let record: js_abi::LogRecord = js_abi::decode(&record_bytes)?;
+                eprintln!("[{}] {}", record.level.as_str(), record.message);
+                return Ok(host_result(js_abi::OK, ""));
+            }
+
+            let record_mem = Memory::from_bytes(record_bytes)?;
+            let ret = unsafe { _apoxy_log(record_mem.offset()) };
+            record_mem.free();
+            Ok(host_result(ret, "Failed to write log record"))
+        },
+    )?;
+
+    Ok(apoxy_log)
+}
+
+fn build_apoxy_kv_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let get = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.kv")?;
+            let Some(entry) = kv::get(key_arg(args)?)? else {
+                return Ok(JSValue::Null);
+            };
+            Ok(JSValue::from_hashmap(HashMap::from([
+                ("value", JSValue::ArrayBuffer(entry.value())),
+                (
+                    "metadata",
+                    entry.metadata().map_or(JSValue::Null, json_value),
+                ),
+            ])))
+        },
+    )?;
+    let put = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.kv")?;
+            let put_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
+            let put: kv::KvPut = js_abi::decode(&put_bytes)?;
+            let value = args.get(1).unwrap().as_bytes()?;
+            kv::put(&put, value)?;
+            Ok(JSValue::Undefined)
+        },
+    )?;
+    let delete = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.kv")?;
+            kv::delete(key_arg(args)?)?;
+            Ok(JSValue::Undefined)
+        },
+    )?;
+    let list = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.kv")?;
+            let list_bytes = messagepack::transcode_output(*(args.first().unwrap()))?;
+            let result = kv::list(&js_abi::decode(&list_bytes)?)?;
+            let keys = result
+                .keys
+                .into_iter()
+                .map(|key| {
+                    JSValue::from_hashmap(HashMap::from([
+                        ("name", JSValue::String(key.name)),
+                        (
+                            "expiration",
+                            key.expiration
+                                .map_or(JSValue::Null, |x| JSValue::Float(x as f64)),
+                        ),
+                        (
+                            "metadata",
+                            key.metadata.as_ref().map_or(JSValue::Null, json_value),
+                        ),
+                    ]))
+                })
+                .collect();
+            Ok(JSValue::from_hashmap(HashMap::from([
+                ("keys", JSValue::Array(keys)),
+                (
+                    "cursor",
+                    result.cursor.map_or(JSValue::Null, JSValue::String),
+                ),
+            ])))
+        },
+    )?;
+
+    let kv_object = context.object_value()?;
+    kv_object.set_property("get", get)?;
+    kv_object.set_property("put", put)?;
+    kv_object.set_property("delete", delete)?;
+    kv_object.set_property("list", list)?;
+    Ok(kv_object)
+}
+
+fn build_apoxy_cache_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    let get = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.cache")?;
+            match cache::get(key_arg(args)?)? {
+                Some(value) => Ok(JSValue::ArrayBuffer(value)),
+                None => Ok(JSValue::Undefined),
+            }
+        },
+    )?;
+    let set = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.cache")?;
+            let value = args
+                .get(1)
+                .ok_or_else(|| anyhow!("[core] Expected a value"))?
+                .as_bytes()?;
+            cache::set(key_arg(args)?, value)?;
+            Ok(JSValue::Undefined)
+        },
+    )?;
+    let delete = context.wrap_callback(
+        |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("Apoxy.cache")?;
+            Ok(JSValue::Bool(cache::delete(key_arg(args)?)?))
+        },
+    )?;
+
+    let cache_object = context.object_value()?;
+    cache_object.set_property("get", get)?;
+    cache_object.set_property("set", set)?;
+    cache_object.set_property("delete", delete)?;
+    Ok(cache_object)
+}
+
+/// Converts metadata from the host into a JavaScript value.
+fn json_value(value: &serde_json::Value) -> JSValue {
+    match value {
+        serde_json::Value::Null => JSValue::Null,
+        serde_json::Value::Bool(x) => JSValue::Bool(*x),
+        serde_json::Value::Number(x) => match x.as_i64().and_then(|x| i32::try_from(x).ok()) {
+            Some(x) => JSValue::Int(x),
+            None => JSValue::Float(x.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(x) => JSValue::String(x.clone()),
+        serde_json::Value::Array(x) => JSValue::Array(x.iter().map(json_value).collect()),
+        serde_json::Value::Object(x) => JSValue::Object(
+            x.iter()
+                .map(|(key, value)| (key.clone(), json_value(value)))
+                .collect(),
+        ),
+    }
+}
+
 fn build_console_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
     let console_debug_callback = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
             let stmt = get_args_as_str(args)?;
-            debug!("{}", stmt);
+            console_log(LogLevel::Debug, &stmt);
             Ok(JSValue::Undefined)
         },
     )?;
     let console_info_callback = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
             let stmt = get_args_as_str(args)?;
-            info!("{}", stmt);
+            console_log(LogLevel::Info, &stmt);
             Ok(JSValue::Undefined)
         },
     )?;
     let console_warn_callback = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
             let stmt = get_args_as_str(args)?;
-            warn!("{}", stmt);
+            console_log(LogLevel::Warn, &stmt);
             Ok(JSValue::Undefined)
         },
     )?;
     let console_error_callback = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
             let stmt = get_args_as_str(args)?;
-            error!("{}", stmt);
+            console_log(LogLevel::Error, &stmt);
             Ok(JSValue::Undefined)
         },
     )?;
@@ -282,6 +647,7 @@ fn build_module_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
 fn build_fetch_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
     let fetch_callback = context.wrap_callback(
         |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            runtime_only("fetch")?;
             let url = args.get(0).unwrap().as_str()?;
             let opts: HashMap<String, JSValue> = args.get(1).unwrap().try_into()?;
 
@@ -296,54 +662,85 @@ fn build_fetch_object(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
             let mut fetch_req = fetch::FetchRequest {
                 url: url.to_string(),
                 method,
-                headers: HashMap::new(),
+                headers: Vec::new(),
             };
 
             let headers = opts.get("headers").unwrap();
-            if let JSValue::Object(headers) = headers {
-                for (key, value) in headers {
-                    fetch_req.headers.insert(key.to_string(), value.to_string());
+            if let JSValue::Array(headers) = headers {
+                for header in headers {
+                    match header {
+                        JSValue::Array(pair) if pair.len() == 2 => fetch_req
+                            .headers
+                            .push((pair[0].to_string(), pair[1].to_string())),
+                        _ => return Err(anyhow!("[core] Invalid header: {}", header)),
+                    }
                 }
             }
 
-            let body = opts.get("body").unwrap_or(&JSValue::Undefined);
-            let mut http_body: Option<String> = None;
-            if let JSValue::String(body) = body {
-                http_body = Some(body.to_string());
-            }
+            // `fetch.js` hands over every body as an `ArrayBuffer`, so bytes
+            // reach the host untouched.
+            let http_body = match opts.get("body").unwrap_or(&JSValue::Undefined) {
+                JSValue::ArrayBuffer(bytes) => Some(bytes.clone()),
+                JSValue::String(body) => Some(body.clone().into_bytes()),
+                JSValue::Null | JSValue::Undefined => None,
+                _ => return Err(anyhow!("[core] Unsupported fetch body")),
+            };
 
-            match fetch::request::<String>(&fetch_req, http_body) {
-                Ok(resp) => {
-                    let parsed_result = HashMap::from([
-                        ("status", JSValue::Int(i32::from(resp.status_code()))),
-                        (
-                            "headers",
-                            JSValue::from_hashmap(
-                                resp.headers()
-                                    .into_iter()
-                                    .map(|(k, v)| (k.as_str(), v.as_str()))
-                                    .collect(),
-                            ),
-                        ),
-                        ("body", JSValue::ArrayBuffer(resp.body())),
-                    ]);
-                    Ok(JSValue::from_hashmap(parsed_result))
-                }
-                Err(e) => Ok(JSValue::from_hashmap(HashMap::from([
-                    ("error", JSValue::Bool(true)),
-                    ("type", JSValue::String("InternalError".to_string())),
-                    ("message", JSValue::String(e.to_string())),
-                ]))),
+            // The response is delivered to `__apoxy_fetch_settle` by the
+            // pending-job loop in `lib.rs`.
+            match fetch::start::<Vec<u8>>(&fetch_req, http_body) {
+                Ok(handle) => Ok(JSValue::from_hashmap(HashMap::from([(
+                    "handle",
+                    JSValue::Float(handle as f64),
+                )]))),
+                Err(e) => Ok(fetch_error(e)),
             }
         },
     )?;
     Ok(fetch_callback)
 }
 
+/// The result of a completed fetch as seen by `fetch.js`.
+pub(crate) fn fetch_result(result: anyhow::Result<fetch::HttpResponse>) -> JSValue {
+    match result {
+        Ok(resp) => JSValue::from_hashmap(HashMap::from([
+            ("status", JSValue::Int(i32::from(resp.status_code()))),
+            (
+                "headers",
+                JSValue::Array(
+                    resp.headers()
+                        .iter()
+                        .map(|(k, v)| {
+                            JSValue::Array(vec![
+                                JSValue::String(k.clone()),
+                                JSValue::String(v.clone()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            ("body", JSValue::ArrayBuffer(resp.body())),
+        ])),
+        Err(e) => fetch_error(e),
+    }
+}
+
+fn fetch_error(e: anyhow::Error) -> JSValue {
+    JSValue::from_hashmap(HashMap::from([
+        ("error", JSValue::Bool(true)),
+        ("type", JSValue::String("InternalError".to_string())),
+        ("message", JSValue::String(e.to_string())),
+    ]))
+}
+
 fn build_clock(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
     context.wrap_callback(get_time())
 }
 
+fn build_monotonic_clock(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
+    context.wrap_callback(get_monotonic_time())
+}
+
 fn build_decoder(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
     context.wrap_callback(decode_utf8_buffer_to_js_string())
 }
@@ -354,6 +751,8 @@ fn build_encoder(context: &JSContextRef) -> anyhow::Result<JSValueRef> {
 
 fn get_time() -> impl FnMut(&JSContextRef, JSValueRef, &[JSValueRef]) -> anyhow::Result<JSValue> {
     move |_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]| {
+        // The time would be frozen into the snapshot.
+        runtime_only("The clock (Date.now() and new Date())")?;
         let now = Utc::now();
         // This format is compatible with JavaScript's Date constructor
         let formatted = now.to_rfc3339_opts(SecondsFormat::Millis, true);
@@ -361,6 +760,24 @@ fn get_time() -> impl FnMut(&JSContextRef, JSValueRef, &[JSValueRef]) -> anyhow:
     }
 }
 
+/// Where `__getMonotonicTime` counts from, set on its first call.
+static CLOCK_ORIGIN: OnceCell<Instant> = OnceCell::new();
+
+/// Milliseconds from an arbitrary origin on the host's monotonic clock, for
+/// measuring durations, or `null` during initialization.
+fn get_monotonic_time(
+) -> impl FnMut(&JSContextRef, JSValueRef, &[JSValueRef]) -> anyhow::Result<JSValue> {
+    move |_ctx: &JSContextRef, _this: JSValueRef, _args: &[JSValueRef]| {
+        // An origin taken while the module is built would be meaningless at
+        // runtime, and callers such as `console.time` shouldn't fail the build.
+        if INITIALIZING.load(Ordering::SeqCst) {
+            return Ok(JSValue::Null);
+        }
+        let origin = CLOCK_ORIGIN.get_or_init(Instant::now);
+        Ok(JSValue::Float(origin.elapsed().as_secs_f64() * 1000.0))
+    }
+}
+
 fn decode_utf8_buffer_to_js_string(
 ) -> impl FnMut(&JSContextRef, JSValueRef, &[JSValueRef]) -> anyhow::Result<JSValue> {
     move |_ctx: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
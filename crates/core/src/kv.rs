@@ -0,0 +1,92 @@
+use extism_pdk::*;
+
+use js_abi::KvValue;
+pub use js_abi::{KvList, KvListResult, KvPut};
+
+/// A value read from the store, with the metadata it was written with.
+pub struct Entry {
+    value: Memory,
+    metadata: Option<serde_json::Value>,
+}
+
+impl Entry {
+    pub fn value(&self) -> Vec<u8> {
+        self.value.to_vec()
+    }
+
+    pub fn metadata(&self) -> Option<&serde_json::Value> {
+        self.metadata.as_ref()
+    }
+}
+
+#[link(wasm_import_module = "extism:host/user")]
+extern "C" {
+    fn _apoxy_kv_get(key: u64) -> u64;
+    fn _apoxy_kv_put(put: u64, value: u64) -> u64;
+    fn _apoxy_kv_delete(key: u64) -> u64;
+    fn _apoxy_kv_list(list: u64) -> u64;
+}
+
+fn block(offs: u64) -> Memory {
+    let len = match offs {
+        js_abi::NONE => 0,
+        _ => unsafe { extism::length_unsafe(offs) },
+    };
+    Memory(MemoryHandle {
+        offset: offs,
+        length: len,
+    })
+}
+
+pub fn get(key: &str) -> Result<Option<Entry>, Error> {
+    let key_mem = Memory::from_bytes(key)?;
+    let offs = unsafe { _apoxy_kv_get(key_mem.offset()) };
+    key_mem.free();
+    if offs == js_abi::NONE {
+        return Ok(None);
+    }
+
+    let resp_mem = block(offs);
+    let resp: KvValue = js_abi::decode(&resp_mem.to_vec())?;
+    resp_mem.free();
+    Ok(Some(Entry {
+        value: block(resp.value_offset),
+        metadata: resp.metadata,
+    }))
+}
+
+pub fn put(put: &KvPut, value: &[u8]) -> Result<(), Error> {
+    let put_mem = Memory::from_bytes(js_abi::encode(put)?)?;
+    let value_mem = Memory::from_bytes(value)?;
+    let ret = unsafe { _apoxy_kv_put(put_mem.offset(), value_mem.offset()) };
+    put_mem.free();
+    value_mem.free();
+    if ret != js_abi::OK {
+        return Err(Error::msg(format!("Failed to write {:?}", put.key)));
+    }
+    Ok(())
+}
+
+pub fn delete(key: &str) -> Result<(), Error> {
+    let key_mem = Memory::from_bytes(key)?;
+    let ret = unsafe { _apoxy_kv_delete(key_mem.offset()) };
+    key_mem.free();
+    if ret != js_abi::OK {
+        return Err(Error::msg(format!("Failed to delete {:?}", key)));
+    }
+    Ok(())
+}
+
+pub fn list(list: &KvList) -> Result<KvListResult, Error> {
+    let list_mem = Memory::from_bytes(js_abi::encode(list)?)?;
+    let offs = unsafe { _apoxy_kv_list(list_mem.offset()) };
+    list_mem.free();
+    if offs == js_abi::NONE {
+        return Err(Error::msg("Failed to list keys"));
+    }
+
+    let resp_mem = block(offs);
+    let resp = js_abi::decode(&resp_mem.to_vec())?;
+    resp_mem.free();
+    Ok(resp)
+}
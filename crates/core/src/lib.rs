@@ -11,45 +11,133 @@
 // (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use anyhow::Context;
 use extism_pdk::*;
 use once_cell::sync::OnceCell;
 use quickjs_wasm_rs::{JSContextRef, JSValue, JSValueRef};
-use std::io;
-use std::io::Read;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+mod cache;
 mod fetch;
 mod globals;
+mod kv;
+mod source;
+mod sourcemap;
+
+use source::{Source, SourceKind};
 
 static mut CONTEXT: OnceCell<JSContextRef> = OnceCell::new();
-static mut USER_CODE: OnceCell<String> = OnceCell::new();
+static mut USER_CODE: OnceCell<Source> = OnceCell::new();
+static EVALUATED: AtomicBool = AtomicBool::new(false);
+
+const SOURCE_PATH: &str = "/apoxy/source";
 
 #[export_name = "wizer.initialize"]
 extern "C" fn init() {
-    let context = JSContextRef::default();
-    globals::inject_globals(&context).expect("Failed to initialize globals");
+    // Written by the CLI into a directory it maps in, see `crates/cli/src/opt.rs`.
+    let code = fs::read(SOURCE_PATH).expect("Failed to read user code");
+    let source = Source::from_bytes(&code).expect("Failed to read user code");
+    unsafe { USER_CODE.set(source).unwrap() };
 
-    let mut code = String::new();
-    io::stdin().read_to_string(&mut code).unwrap();
-    unsafe { USER_CODE.set(code).unwrap() };
-
-    unsafe {
-        CONTEXT.set(context).unwrap();
+    if let Err(e) = initialize(code()) {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
     }
 }
 
 fn js_context<'a>() -> &'a JSContextRef {
     unsafe {
-        if CONTEXT.get().is_none() {
-            init()
-        }
+        CONTEXT.get_or_init(|| {
+            let context = JSContextRef::default();
+            globals::inject_globals(&context).expect("Failed to initialize globals");
+            context
+        })
+    }
+}
+
+fn code() -> &'static Source {
+    unsafe { USER_CODE.get() }.expect("[core] No user code was loaded")
+}
 
-        let context = CONTEXT.get_unchecked();
-        context
+/// Maps a stack trace back to the original sources, if the user's code came
+/// with source maps.
+pub(crate) fn map_stack(stack: &str) -> String {
+    match unsafe { USER_CODE.get() } {
+        Some(source) => sourcemap::map_stack(source, stack),
+        None => stack.to_string(),
     }
 }
 
-fn code() -> &'static str {
-    unsafe { USER_CODE.get_unchecked() }
+/// Evaluates the user's code once. With `Source::initialize` set this happens
+/// in `wizer.initialize`, otherwise in `_start`.
+fn evaluate(context: &JSContextRef) -> anyhow::Result<()> {
+    if EVALUATED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    code()
+        .eval(context)
+        .map_err(|e| anyhow::anyhow!(map_stack(&format!("{:#}", e))))
+}
+
+/// Runs the user's code while the module is built, so the snapshot already
+/// holds its compiled functions and module state, and confirms it registers a
+/// handler. When evaluation is deferred to `_start`, the code runs in a
+/// scratch context only for the check, which is skipped with a warning if
+/// top-level code needs the host.
+fn initialize(source: &Source) -> anyhow::Result<()> {
+    if source.initialize {
+        let context = js_context();
+        globals::set_initializing(true);
+        let result = evaluate(context);
+        globals::set_initializing(false);
+        result.context("Evaluating the code while building the module failed")?;
+        return check_handler(context, source);
+    }
+
+    let context = JSContextRef::default();
+    globals::inject_globals(&context)?;
+    globals::set_initializing(true);
+    let result = source.eval(&context);
+    globals::set_initializing(false);
+    if let Err(e) = result {
+        eprintln!(
+            "warning: couldn't check for a handler, evaluating the code failed: {}",
+            map_stack(&format!("{:#}", e))
+        );
+        return Ok(());
+    }
+    check_handler(&context, source)
+}
+
+/// Fails if the evaluated code registered no handler, and reports how it did.
+fn check_handler(context: &JSContextRef, source: &Source) -> anyhow::Result<()> {
+    let style = context
+        .global_object()?
+        .get_property("__apoxy_handler_style")?
+        .call(&context.undefined_value()?, &[])?;
+    let style = if style.is_null() {
+        None
+    } else {
+        Some(style.as_str()?.to_string())
+    };
+    if style.as_deref() == Some("phases") {
+        eprintln!("Handler: phase handlers for {}", phases(context)?);
+        return Ok(());
+    }
+    let description = match (style.as_deref(), &source.kind) {
+        (Some("serve"), _) => "Apoxy.serve()",
+        (Some("default"), SourceKind::Module) => "export default { fetch }",
+        (Some("default"), SourceKind::Script) => "module.exports.default = { fetch }",
+        (Some("exports"), SourceKind::Module) => "export function fetch",
+        (Some("exports"), SourceKind::Script) => "module.exports = { fetch }",
+        _ => anyhow::bail!(
+            "no handler registered. Call Apoxy.serve(handler), register a phase handler such as Apoxy.onRequest(handler), or export a default object with a fetch method."
+        ),
+    };
+    eprintln!("Handler: {}", description);
+
+    Ok(())
 }
 
 fn convert_js_value<'a>(context: &'a JSContextRef, v: &JSValue) -> JSValueRef<'a> {
@@ -79,6 +167,32 @@ fn convert_js_value<'a>(context: &'a JSContextRef, v: &JSValue) -> JSValueRef<'a
     }
 }
 
+/// Runs pending jobs until the event loop is idle. Whenever jobs run out while
+/// fetches are still in flight, blocks on the host for the next response and
+/// settles its promise, so concurrent fetches overlap on the host.
+pub(crate) fn run_pending(context: &JSContextRef) -> anyhow::Result<()> {
+    loop {
+        while context.is_pending() {
+            context.execute_pending()?;
+        }
+        if !fetch::fetch::in_flight() {
+            return Ok(());
+        }
+
+        let (handle, result) = fetch::fetch::wait()?;
+        let settle = context
+            .global_object()?
+            .get_property("__apoxy_fetch_settle")?;
+        settle.call(
+            &context.undefined_value()?,
+            &[
+                context.value_from_f64(handle as f64)?,
+                convert_js_value(context, &globals::fetch_result(result)),
+            ],
+        )?;
+    }
+}
+
 fn export_names(exports: JSValueRef<'static>) -> anyhow::Result<Vec<String>> {
     let mut properties = exports.properties()?;
     let mut key = properties.next_key()?;
@@ -93,35 +207,145 @@ fn export_names(exports: JSValueRef<'static>) -> anyhow::Result<Vec<String>> {
 
 #[plugin_fn]
 pub fn _start() -> FnResult<()> {
-    let context = js_context();
-    let code = code();
-
-    context.eval_global("script.js", code)?;
+    evaluate(js_context())?;
 
     Ok(())
 }
 
+/// Loads the user's code when the engine is linked as a shared provider rather
+/// than initialized with it, see `crates/cli/src/dynamic.rs`. The generated
+/// module copies its `Source` into the Extism memory block at `offset` and
+/// calls this once, before forwarding its first call to the engine.
+#[no_mangle]
+pub extern "C" fn _apoxy_load(offset: u64) -> i32 {
+    match load(offset) {
+        Ok(()) => 0,
+        Err(e) => {
+            let message = format!("{:#}", e);
+            let memory = Memory::from_bytes(&message).unwrap();
+            unsafe { extism::error_set(memory.offset()) };
+            1
+        }
+    }
+}
+
+fn load(offset: u64) -> anyhow::Result<()> {
+    let memory = Memory::find(offset).context("[core] No user code at the given offset")?;
+    let source = Source::from_bytes(&memory.to_vec())?;
+    memory.free();
+    if unsafe { USER_CODE.set(source) }.is_err() {
+        anyhow::bail!("[core] User code was already loaded");
+    }
+    evaluate(js_context())
+}
+
+/// Compiles the user's code to bytecode. The CLI calls this on the engine
+/// before embedding the code, so it must not touch `USER_CODE`.
+#[plugin_fn]
+pub fn _apoxy_compile(input: Vec<u8>) -> FnResult<Vec<u8>> {
+    let mut source = Source::from_bytes(&input)?;
+    source.compile(&JSContextRef::default())?;
+    Ok(source.to_bytes()?)
+}
+
+/// Marks modules built before `_apoxy_abi`; kept so older hosts still
+/// recognize newer modules.
 #[plugin_fn]
 pub fn _apoxy_sdk_v1alpha() -> FnResult<()> {
     Ok(())
 }
 
+/// Reports the ABI version and features this engine implements, see the
+/// `js-abi` crate. Hosts call it before anything else.
+#[plugin_fn]
+pub fn _apoxy_abi() -> FnResult<Json<js_abi::AbiInfo>> {
+    Ok(Json(js_abi::AbiInfo::current()))
+}
+
 #[plugin_fn]
 pub fn _apoxy_start() -> FnResult<()> {
     let context = js_context();
 
-    let req = javy::json::transcode_input(&context, input_bytes().as_slice())?;
+    globals::take_handler_error();
+
+    let req = javy::messagepack::transcode_input(&context, input_bytes().as_slice())?;
     context
         .global_object()?
         .set_property("__backend_mode", req.get_property("backend_mode")?)?;
-    context
+    run_handler(context, "__handler", req)
+}
+
+/// Lists the phases the user's code registered a handler for, separated by
+/// commas: `request`, `response` and `complete`. The host calls the matching
+/// `_apoxy_on_*` export in each of those phases instead of `_apoxy_start`.
+#[plugin_fn]
+pub fn _apoxy_phases() -> FnResult<String> {
+    Ok(phases(js_context())?)
+}
+
+fn phases(context: &JSContextRef) -> anyhow::Result<String> {
+    let phases = context
         .global_object()?
-        .get_property("__handler")?
-        .call(&context.undefined_value().unwrap(), &[req])?;
+        .get_property("__apoxy_phases")?
+        .call(&context.undefined_value()?, &[])?;
+    Ok(phases.as_str()?.to_string())
+}
+
+/// Runs the `Apoxy.onRequest` handler before the request is forwarded.
+#[plugin_fn]
+pub fn _apoxy_on_request() -> FnResult<()> {
+    run_phase("__apoxy_on_request")
+}
+
+/// Runs the `Apoxy.onResponse` handler with the upstream response.
+#[plugin_fn]
+pub fn _apoxy_on_response() -> FnResult<()> {
+    run_phase("__apoxy_on_response")
+}
+
+/// Runs the `Apoxy.onComplete` handler once the response has been sent.
+#[plugin_fn]
+pub fn _apoxy_on_complete() -> FnResult<()> {
+    run_phase("__apoxy_on_complete")
+}
+
+fn run_phase(handler: &str) -> FnResult<()> {
+    let context = js_context();
+
+    globals::take_handler_error();
+
+    let input = javy::messagepack::transcode_input(&context, input_bytes().as_slice())?;
+    run_handler(context, handler, input)
+}
+
+/// Calls a handler global of the prelude and runs everything it started.
+fn run_handler<'a>(
+    context: &'a JSContextRef,
+    handler: &str,
+    input: JSValueRef<'a>,
+) -> FnResult<()> {
+    // Start from no fetches in flight, as the host does for every request,
+    // even if the previous handler failed while some were.
+    fetch::fetch::reset();
+    let global = context.global_object()?;
+    global
+        .get_property("__apoxy_fetch_reset")?
+        .call(&context.undefined_value()?, &[])?;
+
+    global
+        .get_property(handler)?
+        .call(&context.undefined_value().unwrap(), &[input])?;
+
+    // Execute all pending operations (e.g promises and fetches).
+    run_pending(context)?;
 
-    // Execute all pending operations (e.g promises).
-    while context.is_pending() {
-        context.execute_pending()?;
+    // The error response has been sent by now; the return code tells the
+    // host that the handler failed.
+    if let Some(message) = globals::take_handler_error() {
+        return Err(WithReturnCode::new(
+            anyhow::anyhow!("[core] Handler failed: {}", message),
+            1,
+        ));
     }
 
     Ok(())
@@ -0,0 +1,78 @@
+// Generated from the js-abi crate by `cargo run -p js-abi --bin abi-ts`, do not edit.
+
+/**
+ * Header names and values in order. Names may repeat and are matched
+ * case-insensitively; the prelude sends them lowercased.
+ *
+ * @internal
+ */
+export type HeadersABI = [string, string][];
+
+/**
+ * Severity of a log record.
+ *
+ * @internal
+ */
+export type LogLevelABI = "debug" | "info" | "warn" | "error";
+
+/**
+ * The request metadata: the downstream request handed to the module, or
+ * a request it sends upstream.
+ *
+ * @internal
+ */
+export interface RequestABI {
+  method: string;
+  url: string;
+  proto: string;
+  proto_major: number;
+  proto_minor: number;
+  header: [string, string][];
+  host: string;
+  remote_addr: string;
+  content_len: number;
+}
+
+/**
+ * The response metadata. `content_len` is 0 for a response whose body is
+ * streamed.
+ *
+ * @internal
+ */
+export interface ResponseABI {
+  status_code: number;
+  content_len: number;
+  header: [string, string][];
+}
+
+/**
+ * The input of `_apoxy_start` and `_apoxy_on_request`.
+ *
+ * @internal
+ */
+export interface StartABI extends RequestABI {
+  backend_mode: boolean;
+}
+
+/**
+ * The input of `_apoxy_on_response` and `_apoxy_on_complete`: the
+ * request as forwarded and the head of the response.
+ *
+ * @internal
+ */
+export interface PhaseABI extends RequestABI {
+  response: ResponseABI;
+}
+
+/**
+ * A structured log record written with `Apoxy.log` and passed to
+ * `_apoxy_log`. Hosts emit it as a JSON object with these keys.
+ *
+ * @internal
+ */
+export interface LogRecordABI {
+  level: LogLevelABI;
+  message: string;
+  request_id: string | null;
+  fields: Record<string, unknown>;
+}
@@ -1,34 +1,49 @@
+import { HeadersImpl, RequestImpl, ResponseImpl } from "./http";
+import type { BodySource } from "./http";
+import type { PhaseABI, RequestABI, ResponseABI, StartABI } from "./abi";
+import { redact } from "./env";
+import { setRequest } from "./log";
+
 declare global {
   /**
    * @internal
    */
-  function __apoxy_req_body(abiReq: RequestABI): {
+  function __apoxy_req_body_read(max: number): {
     error: boolean;
     message: string;
-    bytes: Uint8Array;
+    bytes: ArrayBuffer | null;
   };
   /**
    * @internal
    */
   function __apoxy_req_send(
-    obj: RequestImpl,
+    obj: { _abi_response: ResponseABI | null },
+    abiReq: RequestABI,
+    body: ArrayBuffer,
+  ): { error: boolean; message: string };
+  /**
+   * Replaces the request the proxy forwards after the request phase.
+   *
+   * @internal
+   */
+  function __apoxy_req_set(
     abiReq: RequestABI,
     body: ArrayBuffer,
   ): { error: boolean; message: string };
   /**
    * @internal
    */
-  function __apoxy_resp_body(abiRes: ResponseABI): {
+  function __apoxy_resp_body_read(max: number): {
     error: boolean;
     message: string;
-    bytes: Uint8Array;
+    bytes: ArrayBuffer | null;
   };
   /**
    * @internal
    */
   function __apoxy_resp_send(
     abiRes: ResponseABI,
-    body: Uint8Array,
+    body: ArrayBuffer,
   ): {
     error: boolean;
     message: string;
@@ -43,76 +58,126 @@ declare global {
     error: boolean;
     message: string;
   };
+  /**
+   * Sends the head of a response whose body follows in chunks, either
+   * downstream or in place of the upstream response.
+   *
+   * @internal
+   */
+  function __apoxy_resp_stream(
+    abiRes: ResponseABI,
+    downstream: boolean,
+  ): { error: boolean; message: string };
+  /**
+   * @internal
+   */
+  function __apoxy_resp_write(chunk: ArrayBuffer): {
+    error: boolean;
+    message: string;
+  };
+  /**
+   * @internal
+   */
+  function __apoxy_resp_close(): { error: boolean; message: string };
+  /**
+   * Records that the handler failed, so `_apoxy_start` returns an error.
+   *
+   * @internal
+   */
+  function __apoxy_handler_error(message: string): void;
+  /**
+   * Maps the frames of a stack trace back to the original sources.
+   *
+   * @internal
+   */
+  function __apoxy_map_stack(stack: string): string;
 
-  interface Headers {
-    append(name: string, value: string): void;
-
-    delete(name: string): void;
-
-    get(name: string): string | null;
-
-    has(name: string): boolean;
-
-    set(name: string, value: string): void;
-
-    toObject(): Record<string, string>;
-  }
-
-  interface Request {
-    method?:
-      | "GET"
-      | "HEAD"
-      | "POST"
-      | "PUT"
-      | "DELETE"
-      | "CONNECT"
-      | "OPTIONS"
-      | "TRACE"
-      | "PATCH";
-
-    url: string;
-
-    proto: string;
-
-    headers: Headers;
-
-    content_len: number;
-
-    host: string;
-
-    remote_addr: string;
-
-    body(): Uint8Array;
+  /**
+   * The request passed to an `Apoxy.serve` handler: a standard `Request`
+   * along with what the proxy knows about the connection.
+   */
+  interface ApoxyRequest extends Request {
+    readonly proto: string;
 
-    set_body(body: Uint8Array): void;
+    readonly host: string;
 
-    next(): Response;
+    readonly remote_addr: string;
 
-    response(): Response | null;
+    /**
+     * Sends this request, or a replacement built from it, to the upstream
+     * and resolves with the upstream response. Returning that response, or
+     * a new one, from the handler replaces what is sent downstream. Only
+     * available to filters, and not to phase handlers, where the proxy sends
+     * the request itself.
+     */
+    next(request?: Request): Promise<Response>;
   }
 
-  interface Response {
-    code: number;
-    content_len: number;
-
-    status(code: number): Response;
-
-    headers(): Headers;
+  /**
+   * Returning a `Response` answers the request. Filters that return nothing
+   * let the request through to the upstream.
+   */
+  type ServeHandler = (
+    req: ApoxyRequest,
+  ) => Response | void | Promise<Response | void>;
 
-    body(): Uint8Array;
+  /**
+   * Runs before the proxy forwards the request. Returning a `Response`
+   * answers the request without contacting the upstream, returning a
+   * `Request` replaces what is forwarded, and returning nothing forwards the
+   * request as it is.
+   */
+  type RequestHandler = (
+    req: ApoxyRequest,
+  ) => Request | Response | void | Promise<Request | Response | void>;
 
-    send(body: Uint8Array): void;
-  }
+  /**
+   * Runs with the upstream response before it is sent downstream. Returning
+   * a `Response`, such as `res` with changed headers, replaces it.
+   */
+  type ResponseHandler = (
+    req: ApoxyRequest,
+    res: Response,
+  ) => Response | void | Promise<Response | void>;
 
-  type ServeHandler = (req: Request, res: Response) => void;
+  /**
+   * Runs after the response was sent, for logging and metrics. `res` only
+   * carries the status and headers.
+   */
+  type CompleteHandler = (req: ApoxyRequest, res: Response) => void | Promise<void>;
 
+  /**
+   * The plugin config. Values are strings; the typed accessors return `null`
+   * for missing keys and throw when a value doesn't parse.
+   */
   var Env: {
     get(key: string): string | null;
+    keys(): string[];
+    toObject(): Record<string, string>;
+    getJSON<T = unknown>(key: string): T | null;
+    getNumber(key: string): number | null;
+    /** Reads `true`, `1`, `yes` and `on` as true, `false`, `0`, `no` and `off` as false. */
+    getBool(key: string): boolean | null;
+  };
+
+  /**
+   * Secrets, kept apart from the config. Values read here are replaced with
+   * `[REDACTED]` in console output, `Apoxy.log` records and error messages.
+   */
+  var Secrets: {
+    get(key: string): string | null;
   };
 
   var Apoxy: {
     env: typeof Env;
+    secrets: typeof Secrets;
+    log: Logger;
+    kv: KVNamespace;
+    cache: ApoxyCache;
     serve(handler: ServeHandler): void;
+    onRequest(handler: RequestHandler): void;
+    onResponse(handler: ResponseHandler): void;
+    onComplete(handler: CompleteHandler): void;
   };
 
   /**
@@ -124,351 +189,489 @@ declare global {
    * @internal
    */
   var __handler: (req: RequestABI) => void;
-}
 
-Apoxy.serve = new Proxy(Apoxy.serve, {
-  apply(target, thisArg, [handler]) {
-    __handler = (reqABI: RequestABI) => {
-      try {
-        let req: Request;
-        let resp: Response;
-        if (__backend_mode) {
-          req = new BackendRequestImpl(reqABI);
-          resp = new BackendResponseImpl();
-        } else {
-          req = new RequestImpl(reqABI);
-          resp = new FilterResponseImpl();
-        }
+  /**
+   * Names the phases with a registered handler, separated by commas.
+   *
+   * @internal
+   */
+  var __apoxy_phases: () => string;
+  /**
+   * @internal
+   */
+  var __apoxy_on_request: (input: StartABI) => void;
+  /**
+   * @internal
+   */
+  var __apoxy_on_response: (input: PhaseABI) => void;
+  /**
+   * @internal
+   */
+  var __apoxy_on_complete: (input: PhaseABI) => void;
+}
 
-        Promise.resolve(handler!(req, resp))
-          .then(() => {
-            if (__backend_mode) {
-              let backend_resp = resp as BackendResponseImpl;
-              backend_resp.sendDownstream();
-            } else {
-              let filter_resp = resp as FilterResponseImpl;
-              if (filter_resp.sendDownstream()) {
-                console.debug("Sent response downstream");
-                return;
-              }
-              let backend_resp = req.response() as BackendResponseImpl;
-              if (backend_resp) {
-                backend_resp.sendDownstream();
-              }
-            }
-          })
-          .catch((e) => {
-            console.error("[apoxy/js] Exception in handler:", e);
-          });
-      } catch (e) {
-        console.error("[apoxy/js] Exception in handler:", e);
-      }
-    };
-    return Reflect.apply(target, thisArg, [handler]);
-  },
-});
+/** How much of a body is read from the host at a time. */
+const CHUNK_SIZE = 64 * 1024;
 
-interface RequestABI {
-  method: string;
-  url: string;
-  proto: string;
-  proto_major: number;
-  proto_minor: number;
-  header: Record<string, string>;
-  host: string;
-  remote_addr: string;
-  content_len: number;
+function hostBody(
+  read: (max: number) => { error: boolean; message: string; bytes: ArrayBuffer | null },
+): BodySource {
+  return () => {
+    const result = read(CHUNK_SIZE);
+    if (result.error === true) {
+      throw new Error(result.message);
+    }
+    return result.bytes === null ? null : new Uint8Array(result.bytes);
+  };
 }
 
-class HeadersImpl implements Headers {
-  private headers: Record<string, string> = {};
-
-  constructor(headers: Record<string, string> = {}) {
-    this.headers = headers;
+function toArrayBuffer(bytes: Uint8Array | null): ArrayBuffer {
+  if (bytes === null) {
+    return new ArrayBuffer(0);
   }
+  return bytes.buffer.slice(bytes.byteOffset, bytes.byteOffset + bytes.byteLength);
+}
 
-  append(name: string, value: string): void {
-    this.headers = { ...this.headers, [name]: value };
+function sendBuffered(
+  abi: ResponseABI,
+  bytes: Uint8Array | null,
+  downstream: boolean,
+): void {
+  const result = downstream
+    ? __apoxy_send_downstream(abi, toArrayBuffer(bytes))
+    : __apoxy_resp_send(abi, toArrayBuffer(bytes));
+  if (result.error === true) {
+    throw new Error(result.message);
   }
+  console.debug("Sent response downstream");
+}
 
-  delete(name: string): void {
-    delete this.headers[name];
+/** Describes an exception, without the secrets it may contain. */
+function describeError(e: any): { message: string; stack: string } {
+  if (e instanceof Error) {
+    return {
+      message: redact(`${e.name}: ${e.message}`),
+      stack: e.stack ? redact(__apoxy_map_stack(e.stack)) : "",
+    };
   }
+  return { message: redact(String(e)), stack: "" };
+}
 
-  get(name: string): string | null {
-    return this.headers[name] || null;
-  }
+/** Formats an exception for the log, with its stack mapped to the sources. */
+function formatError(e: any): string {
+  const { message, stack } = describeError(e);
+  return stack ? `${message}\n${stack}` : message;
+}
 
-  has(name: string): boolean {
-    return this.headers[name] !== undefined;
-  }
+/**
+ * Builds the response sent when a handler throws. It is configured through
+ * the plugin config: `APOXY_ERROR_STATUS` (default 500) and
+ * `APOXY_ERROR_BODY`, a template in which `{{status}}`, `{{message}}` and
+ * `{{stack}}` are replaced. The message and stack are only filled in when
+ * `APOXY_DEV` is set, so errors don't leak in production.
+ */
+function errorResponse(e: any): ResponseImpl {
+  const configured = Number(Apoxy.env.get("APOXY_ERROR_STATUS") ?? 500);
+  const status =
+    Number.isInteger(configured) && configured >= 200 && configured <= 599
+      ? configured
+      : 500;
+  const dev = ["1", "true", "yes"].includes(
+    (Apoxy.env.get("APOXY_DEV") ?? "").toLowerCase(),
+  );
+  const template =
+    Apoxy.env.get("APOXY_ERROR_BODY") ??
+    (dev ? "{{message}}\n{{stack}}" : "Internal Server Error");
+
+  const { message, stack } = describeError(e);
+  const values: Record<string, string> = {
+    status: String(status),
+    message: dev ? message : "",
+    stack: dev ? stack : "",
+  };
+  const body = template.replace(
+    /\{\{(status|message|stack)\}\}/g,
+    (_, key: string) => values[key],
+  );
+  return new ResponseImpl(body, {
+    status,
+    headers: { "content-type": "text/plain;charset=UTF-8" },
+  });
+}
 
-  set(name: string, value: string): void {
-    this.headers = { ...this.headers, [name]: value };
+/**
+ * Logs and records a handler failure, then sends the error response unless
+ * a response is already on its way.
+ */
+function fail(e: any, send: ((response: ResponseImpl) => void) | null): void {
+  const error = formatError(e);
+  console.error("[apoxy/js] Exception in handler:", error);
+  __apoxy_handler_error(error);
+  if (send === null) {
+    return;
   }
-
-  toObject(): Record<string, string> {
-    return this.headers;
+  try {
+    send(errorResponse(e));
+  } catch (e) {
+    console.error("[apoxy/js] Failed to send error response:", formatError(e));
   }
 }
 
-class RequestImpl implements Request {
-  constructor(obj: RequestABI) {
-    switch (obj.method) {
-      case "GET":
-      case "HEAD":
-      case "POST":
-      case "PUT":
-      case "DELETE":
-      case "CONNECT":
-      case "OPTIONS":
-      case "TRACE":
-      case "PATCH":
-        this.method = obj.method;
-        break;
-      case undefined:
-        this.method = "GET";
-        break;
-      default:
-        throw new Error(`Invalid method: "${obj.method}"`);
-    }
-    this.url = obj.url;
-    this.proto = obj.proto;
-    this.headers = new HeadersImpl(obj.header);
-    this.host = obj.host;
-    this.remote_addr = obj.remote_addr;
-    this.content_len = obj.content_len;
-  }
-
-  method?:
-    | "GET"
-    | "HEAD"
-    | "POST"
-    | "PUT"
-    | "DELETE"
-    | "CONNECT"
-    | "OPTIONS"
-    | "TRACE"
-    | "PATCH";
-
-  url: string;
+/**
+ * @internal
+ */
+export class ApoxyRequestImpl extends RequestImpl implements ApoxyRequest {
+  readonly proto: string;
+  readonly host: string;
+  readonly remote_addr: string;
 
-  proto: string;
-
-  headers: Headers;
-
-  content_len: number = 0;
-
-  host: string;
-
-  remote_addr: string;
-
-  body(): Uint8Array {
-    const result = __apoxy_req_body(this.abiReq());
-    if (result.error === true) {
-      throw new Error(result.message);
+  /**
+   * Set for phase handlers, which can't send the request themselves.
+   *
+   * @internal
+   */
+  _inPhase: boolean = false;
+
+  private _abi: RequestABI;
+  private _sent: boolean = false;
+  private _headSent: boolean = false;
+  private _upstream: { abi: ResponseABI; response: ResponseImpl } | null = null;
+
+  constructor(abi: RequestABI) {
+    const method = abi.method || "GET";
+    super(new URL(abi.url, `http://${abi.host || "localhost"}`).toString(), {
+      method,
+      headers: abi.header,
+    });
+    this.proto = abi.proto;
+    this.host = abi.host;
+    this.remote_addr = abi.remote_addr;
+    this._abi = abi;
+
+    if (method !== "GET" && method !== "HEAD") {
+      this._defer(hostBody(__apoxy_req_body_read));
     }
-
-    this.content_len = result.bytes.length;
-    this._body = result.bytes;
-    console.debug("Received request body from backend");
-    return new Uint8Array(result.bytes);
-  }
-
-  set_body(body: Uint8Array): void {
-    this._body = body;
-    this.content_len = body.length;
-    this._body_set = true;
   }
 
-  next(): Response {
-    console.debug("Sending request to backend");
-    const result = __apoxy_req_send(
-      this,
-      this.abiReq(),
-      this._body_set ? this._body.buffer : new ArrayBuffer(0),
-    );
-    if (result.error === true) {
-      throw new Error(result.message);
+  next(request: Request = this): Promise<Response> {
+    if (__backend_mode) {
+      return Promise.reject(new Error("Method not allowed for backend request"));
+    }
+    if (this._inPhase) {
+      return Promise.reject(
+        new Error("next() is not available to phase handlers, the proxy sends the request"),
+      );
+    }
+    if (this._sent) {
+      return Promise.reject(new Error("Request was already sent upstream"));
     }
+    if (!(request instanceof RequestImpl)) {
+      return Promise.reject(new TypeError("next() expects a Request"));
+    }
+    this._sent = true;
+
+    return this._toABI(request).then(({ abi: abiReq, bytes }) => {
+      console.debug("Sending request to backend");
+      const holder: { _abi_response: ResponseABI | null } = {
+        _abi_response: null,
+      };
+      const result = __apoxy_req_send(holder, abiReq, toArrayBuffer(bytes));
+      if (result.error === true) {
+        throw new Error(result.message);
+      }
 
-    console.debug("Received response from backend");
-    this._response = new BackendResponseImpl(this._abi_response);
-    return this._response;
+      console.debug("Received response from backend");
+      return this._receive(holder._abi_response!, request.url);
+    });
   }
 
-  response(): Response | null {
-    return this._response;
+  /**
+   * Wraps the upstream response, whose body is read from the host.
+   *
+   * @internal
+   */
+  _receive(abi: ResponseABI, url: string): ResponseImpl {
+    const response = ResponseImpl._create(
+      { status: abi.status_code, headers: abi.header, url },
+      hostBody(__apoxy_resp_body_read),
+    );
+    this._upstream = { abi, response };
+    return response;
   }
 
-  private abiReq(): RequestABI {
-    // Parse this.proto into major and minor
-    let proto_major: number;
-    let proto_minor: number;
-    try {
-      const [major, minor] = this.proto.split("/");
-      proto_major = parseInt(major);
-      proto_minor = parseInt(minor);
-    } catch (e) {
-      console.error("Failed to parse proto:", this.proto);
+  /**
+   * Replaces the request the proxy forwards, for a request phase handler
+   * that returned one.
+   *
+   * @internal
+   */
+  _forward(request: Request): Promise<void> {
+    if (!(request instanceof RequestImpl)) {
+      return Promise.reject(new TypeError("Expected a Request"));
     }
-    return {
-      method: this.method!,
-      url: this.url,
-      proto: this.proto,
-      proto_major: proto_major,
-      proto_minor: proto_minor,
-      header: this.headers.toObject(),
-      host: this.host,
-      remote_addr: this.remote_addr,
-      content_len: this.content_len,
-    };
-  }
-  private _body: Uint8Array | null = null;
-  private _body_set: boolean = false;
-  private _abi_response: ResponseABI | null = null;
-  private _response: Response | null = null;
-}
-
-class BackendRequestImpl extends RequestImpl {
-  next(): Response {
-    throw new Error("Method not allowed for backend request");
+    return this._toABI(request).then(({ abi, bytes }) => {
+      const result = __apoxy_req_set(abi, toArrayBuffer(bytes));
+      if (result.error === true) {
+        throw new Error(result.message);
+      }
+    });
   }
 
-  response(): Response | null {
-    return null;
+  private _toABI(
+    request: RequestImpl,
+  ): Promise<{ abi: RequestABI; bytes: Uint8Array | null }> {
+    // The proxy keeps the original body unless a new one is sent. Stream
+    // bodies are read to the end first.
+    const body: Promise<Uint8Array | null> = request._isStream()
+      ? request._buffer()
+      : Promise.resolve(request._loaded() ? request._peek() : null);
+    return body.then((bytes) => {
+      const url = new URL(request.url);
+      const abi: RequestABI = {
+        ...this._abi,
+        method: request.method,
+        url: /^[a-z][a-z0-9+.-]*:/i.test(this._abi.url)
+          ? url.toString()
+          : url.pathname + url.search,
+        header: (request.headers as HeadersImpl)._pairs(),
+        content_len: bytes === null ? this._abi.content_len : bytes.length,
+      };
+      return { abi, bytes };
+    });
   }
-}
-
-interface ResponseABI {
-  status_code: number;
-  content_len: number;
-  header: Record<string, string>;
-}
 
-class FilterResponseImpl implements Response {
-  code: number = 200;
-  content_len: number = 0;
-
-  constructor(json?: string) {
-    if (!json) {
-      return;
+  /**
+   * Sends what the handler returned to the host.
+   *
+   * @internal
+   */
+  _respond(response: Response | void): void | Promise<void> {
+    if (response === undefined || response === null) {
+      if (!__backend_mode) {
+        // Let the request, or the upstream response, through untouched.
+        return;
+      }
+      response = new ResponseImpl();
+    }
+    if (!(response instanceof ResponseImpl)) {
+      throw new TypeError("Handlers must return a Response");
     }
-    const obj: ResponseABI = JSON.parse(json);
-    this.code = obj.status_code;
-    this.content_len = obj.content_len;
-    this._headers = new HeadersImpl(obj.header);
-  }
-
-  status(code: number): Response {
-    this.code = code;
-    this._set = true;
-    return this;
-  }
 
-  headers(): Headers {
-    return this._headers;
-  }
+    const downstream = !__backend_mode && this._upstream === null;
+    const header = (response.headers as HeadersImpl)._pairs();
+    if (response._isStream()) {
+      return this._respondStream(
+        response.body!,
+        { status_code: response.status, content_len: 0, header },
+        downstream,
+      );
+    }
 
-  body(): Uint8Array {
-    return this._body;
+    // An upstream response whose body was never read is modified in place,
+    // so its body does not need to round-trip through the VM.
+    const untouched =
+      this._upstream !== null &&
+      response === this._upstream.response &&
+      !response._loaded();
+    const bytes = untouched ? null : response._peek();
+    const abiResp: ResponseABI = {
+      status_code: response.status,
+      content_len: untouched ? this._upstream!.abi.content_len : bytes?.length ?? 0,
+      header,
+    };
+    sendBuffered(abiResp, bytes, downstream);
+    this._headSent = true;
   }
 
-  send(body: Uint8Array): void {
-    console.debug("Setting response:", body.length);
-    this.content_len = body.length;
-    this._body = body;
-    this._set = true;
+  /**
+   * Handles an exception from the handler or from sending its response.
+   *
+   * @internal
+   */
+  _fail(e: any): void {
+    // Once the head of a streamed response is out, the status can't change.
+    fail(e, this._headSent ? null : (response) => this._respond(response));
   }
 
-  sendDownstream(): boolean {
-    console.debug("Response was altered:", this._set);
-    if (!this._set) {
-      return false;
+  /**
+   * Sends the response head, then the body chunk by chunk as the stream
+   * produces it.
+   */
+  private _respondStream(
+    body: ReadableStream<Uint8Array>,
+    abiResp: ResponseABI,
+    downstream: boolean,
+  ): Promise<void> {
+    const reader = body.getReader();
+    const started = __apoxy_resp_stream(abiResp, downstream);
+    if (started.error === true) {
+      return Promise.reject(new Error(started.message));
     }
+    this._headSent = true;
 
-    console.debug("Sending downstream:", this._body ? this._body.length : 0);
-
-    const abiResp: ResponseABI = {
-      status_code: this.code,
-      content_len: this.content_len,
-      header: this._headers.toObject(),
+    const check = (result: { error: boolean; message: string }) => {
+      if (result.error === true) {
+        throw new Error(result.message);
+      }
     };
-    let body = this._body ? this._body : new Uint8Array(0);
-    if (typeof body === "string") {
-      body = new TextEncoder().encode(body);
-    }
-    const result = __apoxy_send_downstream(abiResp, body.buffer);
-    if (result.error === true) {
-      throw new Error(result.message);
-    }
-    return true;
+    const pump = (): Promise<void> =>
+      reader.read().then(({ value, done }) => {
+        if (done) {
+          check(__apoxy_resp_close());
+          console.debug("Sent response downstream");
+          return;
+        }
+        if (!(value instanceof Uint8Array)) {
+          throw new TypeError("Body chunks must be Uint8Arrays");
+        }
+        check(__apoxy_resp_write(toArrayBuffer(value)));
+        return pump();
+      });
+    return pump();
+  }
+}
+
+/**
+ * Runs a handler for a request from the host and sends its response.
+ *
+ * @internal
+ */
+export function dispatch(
+  reqABI: RequestABI,
+  handle: (req: ApoxyRequestImpl) => Response | void | Promise<Response | void>,
+  passThrough: () => boolean = () => false,
+): void {
+  setRequest(reqABI.header);
+  let req: ApoxyRequestImpl;
+  try {
+    req = new ApoxyRequestImpl(reqABI);
+  } catch (e) {
+    fail(e, (response) => {
+      const bytes = response._peek();
+      sendBuffered(
+        {
+          status_code: response.status,
+          content_len: bytes?.length ?? 0,
+          header: (response.headers as HeadersImpl)._pairs(),
+        },
+        bytes,
+        !__backend_mode,
+      );
+    });
+    return;
   }
 
-  private _headers: HeadersImpl = new HeadersImpl();
-  private _body: Uint8Array | null = null;
-  private _set: boolean = false;
+  Promise.resolve()
+    .then(() => handle(req))
+    .then((resp) => req._respond(resp))
+    .catch((e) => {
+      if (passThrough() && !__backend_mode) {
+        console.warn(
+          "[apoxy/js] Passing request through after exception:",
+          formatError(e),
+        );
+        return;
+      }
+      req._fail(e);
+    });
 }
 
-class BackendResponseImpl implements Response {
-  code: number = 200;
-  content_len: number = 0;
+let served = false;
 
-  constructor(obj?: ResponseABI) {
-    if (!obj) {
-      return;
+/**
+ * Whether the user's code called `Apoxy.serve`.
+ *
+ * @internal
+ */
+export function isServed(): boolean {
+  return served;
+}
+
+Apoxy.serve = new Proxy(Apoxy.serve, {
+  apply(target, thisArg, [handler]) {
+    // Handlers used to be called as `(req, res)` and answer through `res`,
+    // which no longer exists. Fail the build rather than every request.
+    if (typeof handler === "function" && handler.length >= 2) {
+      throw new TypeError(
+        "Apoxy.serve handlers take only the request and return a Response, `(req, res) => ...` handlers are no longer supported. See \"Writing a handler\" in the apoxy-js README to migrate.",
+      );
     }
-    this.code = obj.status_code;
-    this.content_len = obj.content_len;
-    this._headers = new HeadersImpl(obj.header);
-  }
+    served = true;
+    __handler = (reqABI: RequestABI) => dispatch(reqABI, handler);
+    return Reflect.apply(target, thisArg, [handler]);
+  },
+});
 
-  status(code: number): Response {
-    this.code = code;
-    return this;
-  }
+const phases: {
+  request: RequestHandler | null;
+  response: ResponseHandler | null;
+  complete: CompleteHandler | null;
+} = { request: null, response: null, complete: null };
 
-  headers(): Headers {
-    return this._headers;
-  }
+Apoxy.onRequest = (handler: RequestHandler) => {
+  phases.request = handler;
+};
 
-  body(): Uint8Array {
-    const abiResp: ResponseABI = {
-      status_code: this.code,
-      content_len: this.content_len,
-      header: this._headers.toObject(),
-    };
-    const result = __apoxy_resp_body(abiResp);
-    if (result.error === true) {
-      throw new Error(result.message);
-    }
+Apoxy.onResponse = (handler: ResponseHandler) => {
+  phases.response = handler;
+};
 
-    this.content_len = result.bytes.length;
-    this._body = result.bytes;
-    return new Uint8Array(result.bytes);
-  }
+Apoxy.onComplete = (handler: CompleteHandler) => {
+  phases.complete = handler;
+};
 
-  send(body: Uint8Array): void {
-    this.content_len = body.length;
-    this._body = body;
-  }
+__apoxy_phases = () =>
+  (Object.keys(phases) as (keyof typeof phases)[])
+    .filter((phase) => phases[phase] !== null)
+    .join(",");
 
-  sendDownstream(): void {
-    const abiResp: ResponseABI = {
-      status_code: this.code,
-      content_len: this.content_len,
-      header: this._headers.toObject(),
-    };
-    // For the upstream case, we're modifying the response object in place
-    // so we don't need to send the body to the VM.
-    const result = __apoxy_resp_send(abiResp, this._body || new Uint8Array(0));
-    if (result.error === true) {
-      throw new Error(result.message);
-    }
+function phaseHandler<T>(handler: T | null, name: string): T {
+  if (handler === null) {
+    throw new Error(`No ${name} handler registered`);
   }
-
-  private _headers: Headers = new HeadersImpl();
-  private _body: Uint8Array | null = null;
+  return handler;
 }
 
-export {};
+__apoxy_on_request = (input) => {
+  __backend_mode = input.backend_mode;
+  dispatch(input, (req) => {
+    req._inPhase = true;
+    const handler = phaseHandler(phases.request, "onRequest");
+    return Promise.resolve(handler(req)).then((result) =>
+      result instanceof RequestImpl
+        ? req._forward(result)
+        : (result as Response | void),
+    );
+  });
+};
+
+__apoxy_on_response = (input) => {
+  __backend_mode = false;
+  dispatch(input, (req) => {
+    req._inPhase = true;
+    const handler = phaseHandler(phases.response, "onResponse");
+    return handler(req, req._receive(input.response, req.url));
+  });
+};
+
+__apoxy_on_complete = (input) => {
+  __backend_mode = false;
+  setRequest(input.header);
+  // The response is already out, so failures are only logged and recorded.
+  Promise.resolve()
+    .then(() => {
+      const req = new ApoxyRequestImpl(input);
+      req._inPhase = true;
+      const handler = phaseHandler(phases.complete, "onComplete");
+      const res = ResponseImpl._create(
+        {
+          status: input.response.status_code,
+          headers: input.response.header,
+          url: req.url,
+        },
+        null,
+      );
+      return handler(req, res);
+    })
+    .catch((e) => fail(e, null));
+};
@@ -0,0 +1,80 @@
+/**
+ * @internal
+ */
+export function concatBytes(chunks: Uint8Array[]): Uint8Array {
+  const length = chunks.reduce((acc, chunk) => acc + chunk.length, 0);
+  const bytes = new Uint8Array(length);
+  let offset = 0;
+  for (const chunk of chunks) {
+    bytes.set(chunk, offset);
+    offset += chunk.length;
+  }
+  return bytes;
+}
+
+function partToBytes(part: BlobPart): Uint8Array {
+  if (typeof part === "string") {
+    return new TextEncoder().encode(part);
+  }
+  if (part instanceof BlobImpl) {
+    return part._bytes;
+  }
+  if (ArrayBuffer.isView(part)) {
+    return new Uint8Array(part.buffer, part.byteOffset, part.byteLength);
+  }
+  return new Uint8Array(part);
+}
+
+/**
+ * @internal
+ */
+export class BlobImpl implements Blob {
+  /**
+   * @internal
+   */
+  _bytes: Uint8Array;
+
+  readonly type: string;
+
+  constructor(blobParts: BlobPart[] = [], options: BlobPropertyBag = {}) {
+    this._bytes = concatBytes(blobParts.map(partToBytes));
+    this.type = (options.type || "").toLowerCase();
+  }
+
+  get size(): number {
+    return this._bytes.length;
+  }
+
+  arrayBuffer(): Promise<ArrayBuffer> {
+    return Promise.resolve(this._bytes.slice().buffer);
+  }
+
+  text(): Promise<string> {
+    return Promise.resolve(new TextDecoder().decode(this._bytes));
+  }
+
+  slice(start: number = 0, end: number = this.size, contentType = ""): Blob {
+    const clamp = (x: number) =>
+      x < 0 ? Math.max(this.size + x, 0) : Math.min(x, this.size);
+    return new BlobImpl([this._bytes.subarray(clamp(start), clamp(end))], {
+      type: contentType,
+    });
+  }
+}
+
+/**
+ * @internal
+ */
+export class FileImpl extends BlobImpl implements File {
+  readonly name: string;
+  readonly lastModified: number;
+
+  constructor(fileBits: BlobPart[], fileName: string, options: FilePropertyBag = {}) {
+    super(fileBits, options);
+    this.name = String(fileName);
+    this.lastModified = options.lastModified ?? Date.now();
+  }
+}
+
+globalThis.Blob = BlobImpl;
+globalThis.File = FileImpl;
@@ -0,0 +1,103 @@
+declare global {
+  /**
+   * @internal
+   */
+  var __apoxy_cache: {
+    /** Returns `undefined` for keys that aren't cached. */
+    get(key: string): ArrayBuffer | undefined;
+    set(key: string, value: ArrayBuffer): void;
+    delete(key: string): boolean;
+  };
+
+  /**
+   * A cache kept by the module instance, which stays warm across requests.
+   * Unlike `Apoxy.kv` it isn't shared between instances and is lost when the
+   * proxy drops one, so it suits values that can be computed again, such as
+   * fetched keys or parsed config.
+   */
+  interface ApoxyCache {
+    /**
+     * Returns what `set` stored: a JSON value, or an `ArrayBuffer` for bytes.
+     * Keys that aren't cached return `undefined`, which no JSON value decodes
+     * to, so a cached `null` is told apart from a miss.
+     */
+    get<T = unknown>(key: string): T | undefined;
+    /**
+     * Stores bytes or any JSON value. Throws when the value, or the cache as a
+     * whole, would go over its size limit.
+     */
+    set(key: string, value: unknown): void;
+    /** Removes the key, returning whether it was there. */
+    delete(key: string): boolean;
+    /**
+     * Returns the cached value, or computes and caches it. `compute` may be
+     * async, as when fetching a JWKS.
+     */
+    getOrSet<T>(key: string, compute: () => T | Promise<T>): Promise<T>;
+  }
+}
+
+/** The first byte of an entry, telling how the rest is encoded. */
+const BYTES = 0;
+const JSON_TEXT = 1;
+
+function bytes(value: ArrayBuffer | ArrayBufferView): Uint8Array {
+  return value instanceof ArrayBuffer
+    ? new Uint8Array(value)
+    : new Uint8Array(value.buffer, value.byteOffset, value.byteLength);
+}
+
+function encode(value: unknown): ArrayBuffer {
+  let tag: number;
+  let body: Uint8Array;
+  if (value instanceof ArrayBuffer || ArrayBuffer.isView(value)) {
+    tag = BYTES;
+    body = bytes(value);
+  } else {
+    const text = JSON.stringify(value);
+    if (text === undefined) {
+      throw new TypeError("Apoxy.cache: values must be bytes or JSON values");
+    }
+    tag = JSON_TEXT;
+    body = new TextEncoder().encode(text);
+  }
+  const entry = new Uint8Array(body.byteLength + 1);
+  entry[0] = tag;
+  entry.set(body, 1);
+  return entry.buffer;
+}
+
+function decode(entry: ArrayBuffer): unknown {
+  const tag = new Uint8Array(entry, 0, 1)[0];
+  const body = entry.slice(1);
+  return tag === BYTES ? body : JSON.parse(new TextDecoder().decode(body));
+}
+
+const cache: ApoxyCache = {
+  get<T>(key: string) {
+    const entry = __apoxy_cache.get(String(key));
+    return entry === undefined ? undefined : (decode(entry) as T);
+  },
+
+  set(key, value) {
+    __apoxy_cache.set(String(key), encode(value));
+  },
+
+  delete(key) {
+    return __apoxy_cache.delete(String(key));
+  },
+
+  async getOrSet<T>(key: string, compute: () => T | Promise<T>) {
+    const cached = cache.get<T>(key);
+    if (cached !== undefined) {
+      return cached;
+    }
+    const value = await compute();
+    cache.set(key, value);
+    return value;
+  },
+};
+
+Apoxy.cache = cache;
+
+export {};
@@ -0,0 +1,394 @@
+import { redact } from "./env";
+
+declare global {
+  /**
+   * Milliseconds on the host's monotonic clock, or `null` while the module
+   * is built.
+   *
+   * @internal
+   */
+  function __getMonotonicTime(): number | null;
+}
+
+/** Nesting below which objects are abbreviated, as `[Object]`. */
+const MAX_DEPTH = 2;
+/** Entries shown per array, map, set or object before the rest is counted. */
+const MAX_ENTRIES = 100;
+
+const IDENTIFIER = /^[A-Za-z_$][A-Za-z0-9_$]*$/;
+
+function quote(value: string): string {
+  return `'${value.replace(/\\/g, "\\\\").replace(/'/g, "\\'").replace(/\n/g, "\\n")}'`;
+}
+
+function formatKey(key: string | symbol): string {
+  if (typeof key === "symbol") {
+    return `[${key.toString()}]`;
+  }
+  return IDENTIFIER.test(key) ? key : quote(key);
+}
+
+function formatError(e: Error): string {
+  const head = e.message ? `${e.name}: ${e.message}` : e.name;
+  if (!e.stack) {
+    return head;
+  }
+  // QuickJS stacks only hold the frames, unlike those of errors that came
+  // from elsewhere.
+  const stack = e.stack.replace(/\n$/, "");
+  return stack.startsWith(head) ? stack : `${head}\n${stack}`;
+}
+
+/** Joins entries as `{ a, b }`, counting those past `MAX_ENTRIES`. */
+function braces(open: string, entries: string[], total: number, close: string): string {
+  if (total > entries.length) {
+    entries.push(`... ${total - entries.length} more item${total - entries.length > 1 ? "s" : ""}`);
+  }
+  return entries.length === 0 ? `${open}${close}` : `${open} ${entries.join(", ")} ${close}`;
+}
+
+function constructorName(value: object): string | null {
+  const proto = Object.getPrototypeOf(value);
+  if (proto === null) {
+    return null;
+  }
+  const name = proto.constructor?.name;
+  return typeof name === "string" && name !== "" ? name : "Object";
+}
+
+function formatObject(value: object, depth: number, seen: Set<object>): string {
+  if (value instanceof Error) {
+    return formatError(value);
+  }
+  if (value instanceof Date) {
+    return isNaN(value.getTime()) ? "Invalid Date" : value.toISOString();
+  }
+  if (value instanceof RegExp) {
+    return value.toString();
+  }
+  if (value instanceof Promise) {
+    return "Promise {}";
+  }
+  if (seen.has(value)) {
+    return "[Circular]";
+  }
+
+  const name = constructorName(value);
+  if (depth > MAX_DEPTH) {
+    return Array.isArray(value) ? "[Array]" : `[${name ?? "Object"}]`;
+  }
+
+  seen.add(value);
+  try {
+    const inner = (x: unknown) => inspect(x, depth + 1, seen);
+
+    if (Array.isArray(value)) {
+      const items = value.slice(0, MAX_ENTRIES).map(inner);
+      return braces("[", items, value.length, "]");
+    }
+    if (ArrayBuffer.isView(value) && !(value instanceof DataView)) {
+      const array = value as unknown as ArrayLike<number>;
+      const items = Array.from(array).slice(0, MAX_ENTRIES).map(String);
+      return `${name}(${array.length}) ${braces("[", items, array.length, "]")}`;
+    }
+    if (value instanceof ArrayBuffer) {
+      return `ArrayBuffer { byteLength: ${value.byteLength} }`;
+    }
+    if (value instanceof Map) {
+      const items: string[] = [];
+      for (const [k, v] of value) {
+        if (items.length === MAX_ENTRIES) break;
+        items.push(`${inner(k)} => ${inner(v)}`);
+      }
+      return `Map(${value.size}) ${braces("{", items, value.size, "}")}`;
+    }
+    if (value instanceof Set) {
+      const items: string[] = [];
+      for (const v of value) {
+        if (items.length === MAX_ENTRIES) break;
+        items.push(inner(v));
+      }
+      return `Set(${value.size}) ${braces("{", items, value.size, "}")}`;
+    }
+
+    const keys = [
+      ...Object.keys(value),
+      ...Object.getOwnPropertySymbols(value).filter((x) =>
+        Object.prototype.propertyIsEnumerable.call(value, x),
+      ),
+    ];
+    const items = keys
+      .slice(0, MAX_ENTRIES)
+      .map((key) => `${formatKey(key)}: ${inner((value as any)[key])}`);
+    const body = braces("{", items, keys.length, "}");
+    if (name === null) {
+      return `[Object: null prototype] ${body}`;
+    }
+    return name === "Object" ? body : `${name} ${body}`;
+  } finally {
+    seen.delete(value);
+  }
+}
+
+function inspect(value: unknown, depth: number, seen: Set<object>): string {
+  switch (typeof value) {
+    case "string":
+      return depth === 0 ? value : quote(value);
+    case "number":
+      return Object.is(value, -0) ? "-0" : String(value);
+    case "bigint":
+      return `${value}n`;
+    case "symbol":
+      return value.toString();
+    case "undefined":
+      return "undefined";
+    case "function":
+      return value.name ? `[Function: ${value.name}]` : "[Function (anonymous)]";
+    default:
+      return value === null ? "null" : formatObject(value as object, depth, seen);
+  }
+}
+
+/**
+ * Formats a value the way browser consoles print it: strings as they are,
+ * objects and arrays with their contents, errors with their stack.
+ *
+ * @internal
+ */
+export function format(value: unknown): string {
+  return inspect(value, 0, new Set());
+}
+
+/**
+ * Formats `console` arguments. A leading string may hold `%s`, `%d`, `%i`,
+ * `%f`, `%o`, `%O`, `%j` and `%c` substitutions, and the arguments left over
+ * are appended separated by spaces.
+ *
+ * @internal
+ */
+export function formatArgs(args: unknown[]): string {
+  const template = args[0];
+  let rest = args;
+  const parts: string[] = [];
+  if (typeof template === "string" && template.includes("%")) {
+    let index = 1;
+    const first = template.replace(/%([sdifoOjc%])/g, (match, spec: string) => {
+      if (spec === "%") {
+        return "%";
+      }
+      if (index >= args.length) {
+        return match;
+      }
+      const arg = args[index++];
+      switch (spec) {
+        case "s":
+          return typeof arg === "string" ? arg : format(arg);
+        case "d":
+        case "i": {
+          if (typeof arg === "bigint") {
+            return `${arg}n`;
+          }
+          const n = Number(arg);
+          return String(spec === "i" ? Math.trunc(n) : n);
+        }
+        case "f":
+          return String(parseFloat(String(arg)));
+        case "j":
+          try {
+            return JSON.stringify(arg);
+          } catch {
+            return "[Circular]";
+          }
+        case "c":
+          // CSS has no meaning here.
+          return "";
+        default:
+          return inspect(arg, 1, new Set());
+      }
+    });
+    parts.push(first);
+    rest = args.slice(index);
+  } else if (args.length > 0) {
+    parts.push(format(args[0]));
+    rest = args.slice(1);
+  }
+  for (const arg of rest) {
+    parts.push(format(arg));
+  }
+  return parts.join(" ");
+}
+
+const native = {
+  debug: console.debug,
+  info: console.info,
+  warn: console.warn,
+  error: console.error,
+};
+
+type Level = keyof typeof native;
+
+/** Prefixed to every line while inside `console.group`. */
+let indent = "";
+
+function print(level: Level, text: string): void {
+  text = redact(text);
+  native[level](indent === "" ? text : text.replace(/^/gm, indent));
+}
+
+const at =
+  (level: Level) =>
+  (...data: unknown[]) =>
+    print(level, formatArgs(data));
+
+function table(data: unknown, properties?: string[]): string | null {
+  if (typeof data !== "object" || data === null) {
+    return null;
+  }
+  const rows: [string, unknown][] =
+    data instanceof Map
+      ? Array.from(data, ([key, value]): [string, unknown] => [inspect(key, 1, new Set()), value])
+      : data instanceof Set
+        ? Array.from(data, (value, i): [string, unknown] => [String(i), value])
+        : Object.keys(data).map((key): [string, unknown] => [key, (data as any)[key]]);
+
+  const columns: string[] = [];
+  let values = false;
+  for (const [, row] of rows) {
+    if (typeof row === "object" && row !== null && !(row instanceof Map || row instanceof Set)) {
+      for (const key of Object.keys(row)) {
+        if (!columns.includes(key) && (properties === undefined || properties.includes(key))) {
+          columns.push(key);
+        }
+      }
+    } else {
+      values = true;
+    }
+  }
+
+  const cell = (x: unknown) => inspect(x, 1, new Set());
+  const header = ["(index)", ...columns, ...(values ? ["Values"] : [])];
+  const body = rows.map(([index, row]) => {
+    const object = typeof row === "object" && row !== null;
+    return [
+      index,
+      ...columns.map((key) => (object && key in (row as object) ? cell((row as any)[key]) : "")),
+      ...(values ? [object ? "" : cell(row)] : []),
+    ];
+  });
+
+  const widths = header.map((name, i) =>
+    Math.max(name.length, ...body.map((cells) => cells[i].length)),
+  );
+  const line = (left: string, middle: string, right: string) =>
+    left + widths.map((width) => "─".repeat(width + 2)).join(middle) + right;
+  const row = (cells: string[]) =>
+    "│" + cells.map((x, i) => ` ${x.padEnd(widths[i])} `).join("│") + "│";
+  return [
+    line("┌", "┬", "┐"),
+    row(header),
+    line("├", "┼", "┤"),
+    ...body.map(row),
+    line("└", "┴", "┘"),
+  ].join("\n");
+}
+
+const counts = new Map<string, number>();
+const timers = new Map<string, number | null>();
+
+/** Milliseconds since `label` was started, formatted, or null if it wasn't. */
+function elapsed(label: string): string | null {
+  if (!timers.has(label)) {
+    print("warn", `Timer '${label}' does not exist`);
+    return null;
+  }
+  const start = timers.get(label);
+  const now = __getMonotonicTime();
+  if (start === null || start === undefined || now === null) {
+    return `${label}: the clock is not available while the module is built`;
+  }
+  return `${label}: ${(now - start).toFixed(3)}ms`;
+}
+
+console.debug = at("debug");
+console.info = at("info");
+console.log = at("info");
+console.warn = at("warn");
+console.error = at("error");
+console.dir = (item?: unknown) => print("info", format(item));
+console.dirxml = at("info");
+
+console.assert = (condition?: boolean, ...data: unknown[]) => {
+  if (condition) {
+    return;
+  }
+  if (typeof data[0] === "string") {
+    data[0] = `Assertion failed: ${data[0]}`;
+  } else {
+    data.unshift("Assertion failed");
+  }
+  print("error", formatArgs(data));
+};
+
+console.trace = (...data: unknown[]) => {
+  const message = formatArgs(data);
+  // Drop this function's own frame.
+  const stack = (new Error().stack ?? "").split("\n").slice(1).join("\n");
+  print("info", `Trace${message === "" ? "" : `: ${message}`}\n${stack}`.replace(/\n$/, ""));
+};
+
+console.table = (data?: unknown, properties?: string[]) => {
+  print("info", table(data, properties) ?? formatArgs([data]));
+};
+
+console.count = (label: string = "default") => {
+  const count = (counts.get(label) ?? 0) + 1;
+  counts.set(label, count);
+  print("info", `${label}: ${count}`);
+};
+
+console.countReset = (label: string = "default") => {
+  if (!counts.has(label)) {
+    print("warn", `Count for '${label}' does not exist`);
+    return;
+  }
+  counts.set(label, 0);
+};
+
+console.group = (...data: unknown[]) => {
+  if (data.length > 0) {
+    print("info", formatArgs(data));
+  }
+  indent += "  ";
+};
+console.groupCollapsed = console.group;
+console.groupEnd = () => {
+  indent = indent.slice(2);
+};
+
+console.clear = () => {
+  // There is no screen to clear, only the group nesting to reset.
+  indent = "";
+};
+
+console.time = (label: string = "default") => {
+  if (timers.has(label)) {
+    print("warn", `Timer '${label}' already exists`);
+    return;
+  }
+  timers.set(label, __getMonotonicTime());
+};
+
+console.timeLog = (label: string = "default", ...data: unknown[]) => {
+  const time = elapsed(label);
+  if (time !== null) {
+    print("info", formatArgs([time, ...data]));
+  }
+};
+
+console.timeEnd = (label: string = "default") => {
+  const time = elapsed(label);
+  if (time !== null) {
+    timers.delete(label);
+    print("info", time);
+  }
+};
@@ -0,0 +1,109 @@
+const env = Apoxy.env;
+
+env.toObject = () => {
+  const values: Record<string, string> = {};
+  for (const key of env.keys()) {
+    const value = env.get(key);
+    if (value !== null) {
+      values[key] = value;
+    }
+  }
+  return values;
+};
+
+env.getJSON = <T = unknown>(key: string): T | null => {
+  const value = env.get(key);
+  if (value === null) {
+    return null;
+  }
+  try {
+    return JSON.parse(value) as T;
+  } catch (e) {
+    throw new SyntaxError(`Apoxy.env: ${key} is not valid JSON: ${(e as Error).message}`);
+  }
+};
+
+env.getNumber = (key: string) => {
+  const value = env.get(key);
+  if (value === null) {
+    return null;
+  }
+  const number = Number(value);
+  if (value.trim() === "" || isNaN(number)) {
+    throw new TypeError(`Apoxy.env: ${key} is not a number`);
+  }
+  return number;
+};
+
+env.getBool = (key: string) => {
+  const value = env.get(key);
+  if (value === null) {
+    return null;
+  }
+  switch (value.trim().toLowerCase()) {
+    case "true":
+    case "1":
+    case "yes":
+    case "on":
+      return true;
+    case "false":
+    case "0":
+    case "no":
+    case "off":
+      return false;
+    default:
+      throw new TypeError(`Apoxy.env: ${key} is not a boolean`);
+  }
+};
+
+/** Secret values read so far, longest first so overlapping ones are caught. */
+let revealed: string[] = [];
+
+const getSecret = Apoxy.secrets.get;
+
+Apoxy.secrets.get = (key: string) => {
+  const value = getSecret(key);
+  if (value !== null && value !== "" && !revealed.includes(value)) {
+    revealed = [...revealed, value].sort((a, b) => b.length - a.length);
+  }
+  return value;
+};
+
+/**
+ * Replaces the secrets the module has read with `[REDACTED]`.
+ *
+ * @internal
+ */
+export function redact(text: string): string {
+  for (const secret of revealed) {
+    if (text.includes(secret)) {
+      text = text.split(secret).join("[REDACTED]");
+    }
+  }
+  return text;
+}
+
+/**
+ * Redacts every string in a JSON value.
+ *
+ * @internal
+ */
+export function redactJSON(value: unknown): unknown {
+  if (revealed.length === 0) {
+    return value;
+  }
+  if (typeof value === "string") {
+    return redact(value);
+  }
+  if (Array.isArray(value)) {
+    return value.map(redactJSON);
+  }
+  if (typeof value === "object" && value !== null) {
+    const redacted: Record<string, unknown> = {};
+    for (const [key, x] of Object.entries(value)) {
+      redacted[redact(key)] = redactJSON(x);
+    }
+    return redacted;
+  }
+  return value;
+}
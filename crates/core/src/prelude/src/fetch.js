@@ -2,186 +2,77 @@
 // SPDX-License-Identifier: Apache-2.0
 
 import httpStatus from "http-status";
+import { RequestImpl, ResponseImpl } from "./http";
 
-class Headers {
-  constructor(initialHeaders) {
-    let headers = {};
-
-    // Initialize the headers
-    for (const key in initialHeaders) {
-      let value = initialHeaders[key];
-
-      // Allow only string values
-      if (typeof value === "string") {
-        headers[key] = value;
-      }
-    }
-
-    this.headers = headers;
-  }
-
-  append(key, value) {
-    this.headers[key] = value;
-    return value;
-  }
-
-  set(key, value) {
-    this.append(key, value);
-    return value;
-  }
-
-  delete(key) {
-    let dropValue = delete this.headers[key];
-    return dropValue;
-  }
-
-  get(key) {
-    return this.headers[key];
-  }
-
-  toJSON() {
-    return this.headers;
-  }
-}
-
-// The response object to return the project response.
-// It contains different helpers
-class Response {
-  constructor(body, options = {}) {
-    // Process the body
-    if (body instanceof String) {
-      this.body = body.toString();
-    } else {
-      this.body = body;
-    }
-
-    if (options.headers instanceof Headers) {
-      this.headers = options.headers;
-    } else if (options.headers instanceof Object) {
-      this.headers = new Headers(options.headers);
-    } else {
-      this.headers = new Headers({});
-    }
-
-    this.status = options.status || 200;
-    this.statusText = options.statusText || httpStatus[this.status];
-  }
-
-  static redirect(url, status = 307) {
-    return new Response(`Redirecting to ${url}`, {
-      status,
-      headers: {
-        Location: url,
-      },
-    });
-  }
-
-  get ok() {
-    return this.status >= 200 && this.status < 300;
-  }
-
-  defaultEncoding() {
-    return "utf-8";
-  }
-
-  arrayBuffer() {
-    let parsedBody = this.body;
-
-    if (typeof this.body === "string") {
-      try {
-        // For now, we only consider the String|ArrayBuffer option
-        parsedBody = new TextEncoder().encode(this.body);
-      } catch (e) {
-        return Promise.reject(
-          `There was an error encoding the body: ${e}. Please, use the arrayBuffer() and TextDecoder method instead.`,
-        );
-      }
-    }
-
-    return parsedBody;
-  }
-
-  json() {
-    let parsedBody = this.body;
-
-    if (typeof this.body !== "string") {
-      try {
-        // For now, we only consider the String|ArrayBuffer option
-        parsedBody = new TextDecoder(this.defaultEncoding()).decode(this.body);
-      } catch (e) {
-        return Promise.reject(
-          `There was an error decoding the body: ${e}. Please, use the arrayBuffer() and TextDecoder method instead.`,
-        );
-      }
-    }
+(function () {
+  const __fetch = globalThis.__fetch;
+  // Fetches the host has started but not answered yet, by handle.
+  const inFlight = new Map();
 
+  globalThis.fetch = (input, init) => {
+    let request;
     try {
-      return Promise.resolve(JSON.parse(parsedBody));
+      request = new RequestImpl(input, init);
     } catch (e) {
-      return Promise.reject(`The body is not a valid JSON: ${e}`);
+      return Promise.reject(e);
     }
-  }
 
-  text() {
-    let parsedBody = this.body;
+    // The host takes the whole request body, so stream bodies are read to
+    // the end first.
+    return request._buffer().then((body) => {
+      const started = __fetch(request.url, {
+        method: request.method,
+        headers: request.headers._pairs(),
+        body:
+          body === null
+            ? null
+            : body.buffer.slice(body.byteOffset, body.byteOffset + body.byteLength),
+      });
 
-    if (typeof this.body !== "string") {
-      try {
-        // For now, we only consider the String|ArrayBuffer option
-        parsedBody = new TextDecoder(this.defaultEncoding()).decode(this.body);
-      } catch (e) {
-        return Promise.reject(
-          `There was an error decoding the body: ${e}. Please, use the arrayBuffer() and TextDecoder method instead.`,
-        );
+      if (started.error === true) {
+        throw new TypeError(`[${started.type}] ${started.message}`);
       }
-    }
-
-    return parsedBody;
-  }
 
-  toString() {
-    return this.body;
-  }
-}
-
-(function () {
-  const __fetch = globalThis.__fetch;
-  globalThis.fetch = (uri, opts) => {
-    let optsWithDefault = {
-      method: "GET",
-      headers: {},
-      body: null,
-      ...opts,
-    };
+      return new Promise((resolve, reject) => {
+        inFlight.set(started.handle, { request, resolve, reject });
+      });
+    });
+  };
 
-    if (
-      optsWithDefault.body !== null &&
-      typeof optsWithDefault.body !== "string"
-    ) {
-      try {
-        optsWithDefault.body = new TextEncoder().encode(optsWithDefault.body);
-      } catch (e) {
-        return Promise.reject(
-          `There was an error encoding the body: ${e}. Use a String or encode it using TextEncoder.`,
-        );
+  // Called by the core's pending-job loop as each response arrives.
+  Object.defineProperty(globalThis, "__apoxy_fetch_settle", {
+    value: (handle, result) => {
+      const pending = inFlight.get(handle);
+      if (pending === undefined) {
+        return;
       }
-    }
-
-    let result = __fetch(uri, optsWithDefault);
+      inFlight.delete(handle);
 
-    if (result.error === true) {
-      return Promise.reject(new Error(`[${result.type}] ${result.message}`));
-    } else {
-      let response = new Response(result.body, {
-        headers: result.headers,
-        status: result.status,
-      });
-
-      return Promise.resolve(response);
-    }
-  };
+      if (result.error === true) {
+        pending.reject(new TypeError(`[${result.type}] ${result.message}`));
+        return;
+      }
+      pending.resolve(
+        ResponseImpl._create(
+          {
+            status: result.status,
+            statusText: httpStatus[result.status] || "",
+            headers: result.headers,
+            url: pending.request.url,
+          },
+          new Uint8Array(result.body),
+        ),
+      );
+    },
+    enumerable: false,
+  });
+
+  // Called by the core before each handler runs. A handler that failed, or
+  // didn't wait for its fetches, leaves entries the host no longer knows.
+  Object.defineProperty(globalThis, "__apoxy_fetch_reset", {
+    value: () => inFlight.clear(),
+    enumerable: false,
+  });
 
   Reflect.deleteProperty(globalThis, "__fetch");
 })();
-
-export { Headers, Response };
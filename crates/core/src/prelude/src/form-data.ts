@@ -0,0 +1,222 @@
+import { BlobImpl, FileImpl, concatBytes } from "./blob";
+
+function toEntryValue(
+  value: string | Blob,
+  fileName?: string,
+): FormDataEntryValue {
+  if (!(value instanceof BlobImpl)) {
+    return String(value);
+  }
+  if (value instanceof FileImpl && fileName === undefined) {
+    return value;
+  }
+  return new FileImpl([value], fileName ?? "blob", { type: value.type });
+}
+
+function escapeName(name: string): string {
+  return name
+    .replace(/\r/g, "%0D")
+    .replace(/\n/g, "%0A")
+    .replace(/"/g, "%22");
+}
+
+function indexOf(haystack: Uint8Array, needle: Uint8Array, from: number): number {
+  outer: for (let i = from; i <= haystack.length - needle.length; i++) {
+    for (let j = 0; j < needle.length; j++) {
+      if (haystack[i + j] !== needle[j]) {
+        continue outer;
+      }
+    }
+    return i;
+  }
+  return -1;
+}
+
+function dispositionParam(disposition: string, key: string): string | null {
+  const match = new RegExp(`;\\s*${key}="([^"]*)"`, "i").exec(disposition);
+  return match ? match[1] : null;
+}
+
+/**
+ * @internal
+ */
+export class FormDataImpl implements FormData {
+  private _entries: [string, FormDataEntryValue][] = [];
+
+  append(name: string, value: string | Blob, fileName?: string): void {
+    this._entries.push([String(name), toEntryValue(value, fileName)]);
+  }
+
+  delete(name: string): void {
+    this._entries = this._entries.filter(([key]) => key !== name);
+  }
+
+  get(name: string): FormDataEntryValue | null {
+    const entry = this._entries.find(([key]) => key === name);
+    return entry ? entry[1] : null;
+  }
+
+  getAll(name: string): FormDataEntryValue[] {
+    return this._entries.filter(([key]) => key === name).map(([, v]) => v);
+  }
+
+  has(name: string): boolean {
+    return this._entries.some(([key]) => key === name);
+  }
+
+  set(name: string, value: string | Blob, fileName?: string): void {
+    const entry: [string, FormDataEntryValue] = [
+      String(name),
+      toEntryValue(value, fileName),
+    ];
+    const i = this._entries.findIndex(([key]) => key === entry[0]);
+    if (i === -1) {
+      this._entries.push(entry);
+      return;
+    }
+    this._entries[i] = entry;
+    this._entries = this._entries.filter(
+      ([key], j) => key !== entry[0] || j === i,
+    );
+  }
+
+  forEach(
+    callbackfn: (value: FormDataEntryValue, key: string, parent: FormData) => void,
+    thisArg?: any,
+  ): void {
+    for (const [key, value] of this._entries) {
+      callbackfn.call(thisArg, value, key, this);
+    }
+  }
+
+  *entries(): IterableIterator<[string, FormDataEntryValue]> {
+    yield* this._entries.map(
+      ([key, value]) => [key, value] as [string, FormDataEntryValue],
+    );
+  }
+
+  *keys(): IterableIterator<string> {
+    for (const [key] of this._entries) {
+      yield key;
+    }
+  }
+
+  *values(): IterableIterator<FormDataEntryValue> {
+    for (const [, value] of this._entries) {
+      yield value;
+    }
+  }
+
+  [Symbol.iterator](): IterableIterator<[string, FormDataEntryValue]> {
+    return this.entries();
+  }
+
+  /**
+   * Serializes the entries as `multipart/form-data`.
+   *
+   * @internal
+   */
+  _encode(): { bytes: Uint8Array; boundary: string } {
+    const boundary =
+      "----ApoxyFormBoundary" +
+      Math.random().toString(16).slice(2) +
+      Math.random().toString(16).slice(2);
+    const encoder = new TextEncoder();
+    const chunks: Uint8Array[] = [];
+    for (const [name, value] of this._entries) {
+      let head = `--${boundary}\r\nContent-Disposition: form-data; name="${escapeName(name)}"`;
+      if (typeof value === "string") {
+        chunks.push(encoder.encode(`${head}\r\n\r\n${value}\r\n`));
+        continue;
+      }
+      head += `; filename="${escapeName(value.name)}"\r\nContent-Type: ${
+        value.type || "application/octet-stream"
+      }\r\n\r\n`;
+      chunks.push(encoder.encode(head), (value as FileImpl)._bytes);
+      chunks.push(encoder.encode("\r\n"));
+    }
+    chunks.push(encoder.encode(`--${boundary}--\r\n`));
+    return { bytes: concatBytes(chunks), boundary };
+  }
+
+  /**
+   * Parses a `multipart/form-data` or `application/x-www-form-urlencoded`
+   * body.
+   *
+   * @internal
+   */
+  static _parse(bytes: Uint8Array, contentType: string | null): FormDataImpl {
+    const form = new FormDataImpl();
+    const type = (contentType || "").toLowerCase();
+
+    if (type.startsWith("application/x-www-form-urlencoded")) {
+      new URLSearchParams(new TextDecoder().decode(bytes)).forEach(
+        (value, key) => form.append(key, value),
+      );
+      return form;
+    }
+
+    const boundary = /boundary="?([^";]+)"?/i.exec(contentType || "");
+    if (!type.startsWith("multipart/form-data") || !boundary) {
+      throw new TypeError("Could not parse content as FormData.");
+    }
+
+    const encoder = new TextEncoder();
+    const decoder = new TextDecoder();
+    const delimiter = encoder.encode(`--${boundary[1]}`);
+    const headerEnd = encoder.encode("\r\n\r\n");
+
+    let start = indexOf(bytes, delimiter, 0);
+    while (start !== -1) {
+      const partStart = start + delimiter.length;
+      // `--` after the delimiter marks the end of the body.
+      if (bytes[partStart] === 0x2d && bytes[partStart + 1] === 0x2d) {
+        break;
+      }
+      const next = indexOf(bytes, delimiter, partStart);
+      if (next === -1) {
+        throw new TypeError("Could not parse content as FormData.");
+      }
+
+      // Skip the CRLF after the delimiter, drop the one before the next.
+      const part = bytes.subarray(partStart + 2, next - 2);
+      const split = indexOf(part, headerEnd, 0);
+      if (split === -1) {
+        throw new TypeError("Could not parse content as FormData.");
+      }
+      const headers = decoder.decode(part.subarray(0, split)).split("\r\n");
+      const content = part.subarray(split + headerEnd.length);
+
+      let disposition = "";
+      let partType = "";
+      for (const header of headers) {
+        const [name, ...rest] = header.split(":");
+        const value = rest.join(":").trim();
+        if (name.trim().toLowerCase() === "content-disposition") {
+          disposition = value;
+        } else if (name.trim().toLowerCase() === "content-type") {
+          partType = value;
+        }
+      }
+
+      const name = dispositionParam(disposition, "name");
+      if (name !== null) {
+        const fileName = dispositionParam(disposition, "filename");
+        if (fileName === null) {
+          form.append(name, decoder.decode(content));
+        } else {
+          form.append(
+            name,
+            new FileImpl([content.slice()], fileName, { type: partType }),
+          );
+        }
+      }
+
+      start = next;
+    }
+
+    return form;
+  }
+}
+
+globalThis.FormData = FormDataImpl;
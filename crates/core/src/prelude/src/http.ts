@@ -0,0 +1,600 @@
+import { BlobImpl, concatBytes } from "./blob";
+import { FormDataImpl } from "./form-data";
+import { ReadableStreamImpl } from "./streams";
+
+const TOKEN = /^[!#$%&'*+\-.^_`|~0-9A-Za-z]+$/;
+
+function normalizeName(name: string): string {
+  name = String(name);
+  if (!TOKEN.test(name)) {
+    throw new TypeError(`Invalid header name: "${name}"`);
+  }
+  return name.toLowerCase();
+}
+
+function normalizeValue(value: string): string {
+  return String(value).replace(/^[\t\n\r ]+|[\t\n\r ]+$/g, "");
+}
+
+/**
+ * @internal
+ */
+export class HeadersImpl implements Headers {
+  private _list: [string, string][] = [];
+
+  constructor(init?: HeadersInit) {
+    if (init instanceof HeadersImpl) {
+      this._list = init._list.map(([name, value]) => [name, value]);
+    } else if (init && typeof (init as any)[Symbol.iterator] === "function") {
+      for (const pair of init as Iterable<string[]>) {
+        if (pair.length !== 2) {
+          throw new TypeError("Header pairs must contain exactly two items");
+        }
+        this.append(pair[0], pair[1]);
+      }
+    } else if (init) {
+      const record = init as Record<string, string>;
+      for (const name of Object.keys(record)) {
+        this.append(name, record[name]);
+      }
+    }
+  }
+
+  append(name: string, value: string): void {
+    this._list.push([normalizeName(name), normalizeValue(value)]);
+  }
+
+  delete(name: string): void {
+    const key = normalizeName(name);
+    this._list = this._list.filter(([k]) => k !== key);
+  }
+
+  get(name: string): string | null {
+    const key = normalizeName(name);
+    const values = this._list.filter(([k]) => k === key).map(([, v]) => v);
+    return values.length > 0 ? values.join(", ") : null;
+  }
+
+  getSetCookie(): string[] {
+    return this._list.filter(([k]) => k === "set-cookie").map(([, v]) => v);
+  }
+
+  has(name: string): boolean {
+    const key = normalizeName(name);
+    return this._list.some(([k]) => k === key);
+  }
+
+  set(name: string, value: string): void {
+    const key = normalizeName(name);
+    const entry: [string, string] = [key, normalizeValue(value)];
+    const i = this._list.findIndex(([k]) => k === key);
+    if (i === -1) {
+      this._list.push(entry);
+      return;
+    }
+    this._list[i] = entry;
+    this._list = this._list.filter(([k], j) => k !== key || j === i);
+  }
+
+  forEach(
+    callbackfn: (value: string, key: string, parent: Headers) => void,
+    thisArg?: any,
+  ): void {
+    for (const [key, value] of this) {
+      callbackfn.call(thisArg, value, key, this);
+    }
+  }
+
+  *entries(): IterableIterator<[string, string]> {
+    const names = Array.from(new Set(this._list.map(([k]) => k))).sort();
+    for (const name of names) {
+      if (name === "set-cookie") {
+        // Cookies can't be combined, so each one is its own entry.
+        for (const [k, v] of this._list) {
+          if (k === name) {
+            yield [name, v];
+          }
+        }
+      } else {
+        yield [name, this.get(name)!];
+      }
+    }
+  }
+
+  *keys(): IterableIterator<string> {
+    for (const [key] of this) {
+      yield key;
+    }
+  }
+
+  *values(): IterableIterator<string> {
+    for (const [, value] of this) {
+      yield value;
+    }
+  }
+
+  [Symbol.iterator](): IterableIterator<[string, string]> {
+    return this.entries();
+  }
+
+  /**
+   * Every header in insertion order, as carried by the host ABI.
+   *
+   * @internal
+   */
+  _pairs(): [string, string][] {
+    return this._list.map(([name, value]) => [name, value]);
+  }
+}
+
+/**
+ * @internal
+ */
+export function extractBody(body: BodyInit | null | undefined): {
+  bytes: Uint8Array | null;
+  stream: ReadableStreamImpl<Uint8Array> | null;
+  type: string | null;
+} {
+  if (body === null || body === undefined) {
+    return { bytes: null, stream: null, type: null };
+  }
+  if (body instanceof ReadableStreamImpl) {
+    if (body.locked || body._disturbed) {
+      throw new TypeError("ReadableStream is locked or has been read");
+    }
+    return { bytes: null, stream: body, type: null };
+  }
+  if (typeof body === "string") {
+    return {
+      bytes: new TextEncoder().encode(body),
+      stream: null,
+      type: "text/plain;charset=UTF-8",
+    };
+  }
+  if (body instanceof URLSearchParams) {
+    return {
+      bytes: new TextEncoder().encode(body.toString()),
+      stream: null,
+      type: "application/x-www-form-urlencoded;charset=UTF-8",
+    };
+  }
+  if (body instanceof FormDataImpl) {
+    const { bytes, boundary } = body._encode();
+    return {
+      bytes,
+      stream: null,
+      type: `multipart/form-data; boundary=${boundary}`,
+    };
+  }
+  if (body instanceof BlobImpl) {
+    return { bytes: body._bytes.slice(), stream: null, type: body.type || null };
+  }
+  if (body instanceof ArrayBuffer) {
+    return { bytes: new Uint8Array(body.slice(0)), stream: null, type: null };
+  }
+  if (ArrayBuffer.isView(body)) {
+    return {
+      bytes: new Uint8Array(
+        body.buffer.slice(body.byteOffset, body.byteOffset + body.byteLength),
+      ),
+      stream: null,
+      type: null,
+    };
+  }
+  return {
+    bytes: new TextEncoder().encode(String(body)),
+    stream: null,
+    type: "text/plain;charset=UTF-8",
+  };
+}
+
+/**
+ * Returns the next chunk of a body held by the host, or null once it has all
+ * been read.
+ *
+ * @internal
+ */
+export type BodySource = () => Uint8Array | null;
+
+function readAll(stream: ReadableStreamImpl<Uint8Array>): Promise<Uint8Array> {
+  const reader = stream.getReader();
+  const chunks: Uint8Array[] = [];
+  const pump = (): Promise<Uint8Array> =>
+    reader.read().then(({ value, done }) => {
+      if (done) {
+        reader.releaseLock();
+        return concatBytes(chunks);
+      }
+      if (!(value instanceof Uint8Array)) {
+        throw new TypeError("Body chunks must be Uint8Arrays");
+      }
+      chunks.push(value);
+      return pump();
+    });
+  return pump();
+}
+
+/**
+ * The body mixin shared by `Request` and `Response`. A body is held in one of
+ * three ways: as bytes, as a source that reads chunks from the host on
+ * demand, or as a `ReadableStream`. Reading `body` turns either of the first
+ * two into a stream.
+ *
+ * @internal
+ */
+export abstract class BodyImpl implements Body {
+  abstract readonly headers: Headers;
+
+  protected _bytes: Uint8Array | null = null;
+  protected _source: BodySource | null = null;
+  protected _stream: ReadableStreamImpl<Uint8Array> | null = null;
+  protected _used: boolean = false;
+
+  get body(): ReadableStream<Uint8Array> | null {
+    if (this._stream === null && this._source !== null) {
+      const source = this._source;
+      this._source = null;
+      this._stream = new ReadableStreamImpl<Uint8Array>(
+        {
+          pull(controller) {
+            const chunk = source();
+            if (chunk === null) {
+              controller.close();
+            } else {
+              controller.enqueue(chunk);
+            }
+          },
+        },
+        // Only read from the host when asked to.
+        { highWaterMark: 0 },
+      );
+    } else if (this._stream === null && this._bytes !== null) {
+      const bytes = this._bytes;
+      this._bytes = null;
+      this._stream = new ReadableStreamImpl<Uint8Array>({
+        start(controller) {
+          controller.enqueue(bytes);
+          controller.close();
+        },
+      });
+    }
+    return this._stream;
+  }
+
+  get bodyUsed(): boolean {
+    return this._used || (this._stream !== null && this._stream._disturbed);
+  }
+
+  arrayBuffer(): Promise<ArrayBuffer> {
+    return this._consume().then((bytes) =>
+      bytes.buffer.slice(bytes.byteOffset, bytes.byteOffset + bytes.byteLength),
+    );
+  }
+
+  blob(): Promise<Blob> {
+    return this._consume().then(
+      (bytes) =>
+        new BlobImpl([bytes], { type: this.headers.get("content-type") || "" }),
+    );
+  }
+
+  formData(): Promise<FormData> {
+    return this._consume().then((bytes) =>
+      FormDataImpl._parse(bytes, this.headers.get("content-type")),
+    );
+  }
+
+  json(): Promise<any> {
+    return this.text().then((text) => JSON.parse(text));
+  }
+
+  text(): Promise<string> {
+    return this._consume().then((bytes) => new TextDecoder().decode(bytes));
+  }
+
+  /**
+   * Reads the body from the host as it is needed.
+   *
+   * @internal
+   */
+  _defer(source: BodySource): void {
+    this._bytes = null;
+    this._source = source;
+  }
+
+  /**
+   * Whether the body is available without a host call.
+   *
+   * @internal
+   */
+  _loaded(): boolean {
+    return this._source === null;
+  }
+
+  /**
+   * Whether the body is a stream, which can only be read asynchronously.
+   *
+   * @internal
+   */
+  _isStream(): boolean {
+    return this._stream !== null;
+  }
+
+  /** @internal */
+  _hasBody(): boolean {
+    return this._bytes !== null || this._source !== null || this._stream !== null;
+  }
+
+  /**
+   * The body bytes, without marking the body as used. Stream bodies must be
+   * read with `_buffer` instead.
+   *
+   * @internal
+   */
+  _peek(): Uint8Array | null {
+    if (this._stream !== null) {
+      throw new TypeError("Stream bodies can only be read asynchronously");
+    }
+    if (this._source !== null) {
+      const chunks: Uint8Array[] = [];
+      for (let chunk = this._source(); chunk !== null; chunk = this._source()) {
+        chunks.push(chunk);
+      }
+      this._bytes = concatBytes(chunks);
+      this._source = null;
+    }
+    return this._bytes;
+  }
+
+  /**
+   * The body bytes, reading a stream body to the end.
+   *
+   * @internal
+   */
+  _buffer(): Promise<Uint8Array | null> {
+    if (this._stream === null) {
+      try {
+        return Promise.resolve(this._peek());
+      } catch (e) {
+        return Promise.reject(e);
+      }
+    }
+    if (this.bodyUsed || this._stream.locked) {
+      return Promise.reject(new TypeError("Body has already been used"));
+    }
+    this._used = true;
+    return readAll(this._stream);
+  }
+
+  protected _setBody(body: BodyInit | null | undefined): void {
+    const { bytes, stream, type } = extractBody(body);
+    this._bytes = bytes;
+    this._stream = stream;
+    if (type !== null && !this.headers.has("content-type")) {
+      this.headers.set("content-type", type);
+    }
+  }
+
+  /** Moves the body of `from` to this request or response. */
+  protected _takeBody(from: BodyImpl): void {
+    this._bytes = from._bytes;
+    this._source = from._source;
+    this._stream = from._stream;
+    from._used = from._hasBody();
+    from._bytes = null;
+    from._source = null;
+    from._stream = null;
+  }
+
+  /** Gives `into` a copy of the body that can be read separately. */
+  protected _cloneBody(into: BodyImpl): void {
+    if (this._stream !== null) {
+      const [a, b] = this._stream.tee();
+      this._stream = a as ReadableStreamImpl<Uint8Array>;
+      into._stream = b as ReadableStreamImpl<Uint8Array>;
+      return;
+    }
+    const bytes = this._peek();
+    into._bytes = bytes === null ? null : bytes.slice();
+  }
+
+  private _consume(): Promise<Uint8Array> {
+    if (this.bodyUsed) {
+      return Promise.reject(new TypeError("Body has already been used"));
+    }
+    if (!this._hasBody()) {
+      return Promise.resolve(new Uint8Array(0));
+    }
+    return this._buffer().then((bytes) => {
+      this._used = true;
+      return bytes ?? new Uint8Array(0);
+    });
+  }
+}
+
+const NORMALIZED_METHODS = ["DELETE", "GET", "HEAD", "OPTIONS", "POST", "PUT"];
+const FORBIDDEN_METHODS = ["CONNECT", "TRACE", "TRACK"];
+
+function normalizeMethod(method: string): string {
+  if (!TOKEN.test(method)) {
+    throw new TypeError(`Invalid method: "${method}"`);
+  }
+  const upper = method.toUpperCase();
+  if (FORBIDDEN_METHODS.includes(upper)) {
+    throw new TypeError(`Forbidden method: "${method}"`);
+  }
+  return NORMALIZED_METHODS.includes(upper) ? upper : method;
+}
+
+/**
+ * @internal
+ */
+export class RequestImpl extends BodyImpl implements Request {
+  readonly url: string;
+  readonly method: string;
+  readonly headers: Headers;
+  readonly redirect: RequestRedirect;
+
+  constructor(input: RequestInfo, init: RequestInit = {}) {
+    super();
+
+    let inherited: RequestImpl | null = null;
+    if (input instanceof RequestImpl) {
+      if (input.bodyUsed) {
+        throw new TypeError("Cannot construct a Request from a used Request");
+      }
+      inherited = input;
+      this.url = input.url;
+    } else {
+      try {
+        this.url = new URL(String(input)).toString();
+      } catch (e) {
+        throw new TypeError(`Invalid URL: "${input}"`);
+      }
+    }
+
+    this.method =
+      init.method !== undefined
+        ? normalizeMethod(init.method)
+        : inherited
+          ? inherited.method
+          : "GET";
+    this.headers = new HeadersImpl(init.headers ?? inherited?.headers);
+    this.redirect = init.redirect ?? inherited?.redirect ?? "follow";
+
+    const hasBody =
+      (init.body !== undefined && init.body !== null) ||
+      (init.body === undefined && inherited !== null && inherited._hasBody());
+    if (hasBody && (this.method === "GET" || this.method === "HEAD")) {
+      throw new TypeError("Request with GET/HEAD method cannot have body");
+    }
+
+    if (init.body !== undefined) {
+      this._setBody(init.body);
+    } else if (inherited !== null) {
+      this._takeBody(inherited);
+    }
+  }
+
+  clone(): Request {
+    if (this.bodyUsed) {
+      throw new TypeError("Cannot clone a Request whose body was used");
+    }
+    const clone = new RequestImpl(this.url, {
+      method: this.method,
+      headers: this.headers,
+      redirect: this.redirect,
+    });
+    this._cloneBody(clone);
+    return clone;
+  }
+}
+
+const NULL_BODY_STATUSES = [101, 103, 204, 205, 304];
+const REDIRECT_STATUSES = [301, 302, 303, 307, 308];
+
+/**
+ * @internal
+ */
+export class ResponseImpl extends BodyImpl implements Response {
+  readonly status: number;
+  readonly statusText: string;
+  readonly headers: Headers;
+  readonly type: ResponseType = "default";
+  readonly url: string = "";
+  readonly redirected: boolean = false;
+
+  constructor(body?: BodyInit | null, init: ResponseInit = {}) {
+    super();
+
+    const status = init.status ?? 200;
+    if (status < 200 || status > 599) {
+      throw new RangeError(`Invalid status code: ${status}`);
+    }
+    if (body !== undefined && body !== null && NULL_BODY_STATUSES.includes(status)) {
+      throw new TypeError(`Response with status ${status} cannot have a body`);
+    }
+
+    this.status = status;
+    this.statusText = init.statusText ?? "";
+    this.headers = new HeadersImpl(init.headers);
+    this._setBody(body);
+  }
+
+  get ok(): boolean {
+    return this.status >= 200 && this.status < 300;
+  }
+
+  clone(): Response {
+    if (this.bodyUsed) {
+      throw new TypeError("Cannot clone a Response whose body was used");
+    }
+    const clone = ResponseImpl._create(
+      {
+        status: this.status,
+        statusText: this.statusText,
+        headers: this.headers,
+        url: this.url,
+      },
+      null,
+    );
+    this._cloneBody(clone);
+    return clone;
+  }
+
+  static error(): Response {
+    const response = new ResponseImpl();
+    return Object.assign(response, { status: 0, type: "error" });
+  }
+
+  static json(data: any, init: ResponseInit = {}): Response {
+    const headers = new HeadersImpl(init.headers);
+    if (!headers.has("content-type")) {
+      headers.set("content-type", "application/json");
+    }
+    return new ResponseImpl(JSON.stringify(data), { ...init, headers });
+  }
+
+  static redirect(url: string | URL, status: number = 302): Response {
+    if (!REDIRECT_STATUSES.includes(status)) {
+      throw new RangeError(`Invalid redirect status: ${status}`);
+    }
+    return new ResponseImpl(null, {
+      status,
+      headers: { location: new URL(String(url)).toString() },
+    });
+  }
+
+  /**
+   * Builds a response received from the host, which may carry statuses the
+   * constructor rejects and may load its body lazily.
+   *
+   * @internal
+   */
+  static _create(
+    init: {
+      status: number;
+      statusText?: string;
+      headers?: HeadersInit;
+      url?: string;
+    },
+    body: Uint8Array | BodySource | null,
+  ): ResponseImpl {
+    const response = new ResponseImpl(null, { headers: init.headers });
+    Object.assign(response, {
+      status: init.status,
+      statusText: init.statusText ?? "",
+      url: init.url ?? "",
+    });
+    if (typeof body === "function") {
+      response._defer(body);
+    } else {
+      response._bytes = body;
+    }
+    return response;
+  }
+}
+
+globalThis.Headers = HeadersImpl;
+globalThis.Request = RequestImpl;
+globalThis.Response = ResponseImpl;
@@ -8,8 +8,18 @@ import "core-js/actual/url/to-json";
 import "core-js/actual/url-search-params";
 import "urlpattern-polyfill";
 
+import "./console";
 import "./apoxy";
+import "./blob";
+import "./cache";
 import "./date";
+import "./env";
 import "./fetch";
+import "./form-data";
+import "./http";
+import "./kv";
+import "./log";
+import "./streams";
 import "./text-decoder";
 import "./text-encoder";
+import "./worker";
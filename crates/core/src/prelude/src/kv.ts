@@ -0,0 +1,159 @@
+declare global {
+  /**
+   * @internal
+   */
+  var __apoxy_kv: {
+    get(key: string): { value: ArrayBuffer; metadata: unknown } | null;
+    put(
+      options: { key: string; expiration_ttl: number | null; metadata: unknown },
+      value: ArrayBuffer,
+    ): void;
+    delete(key: string): void;
+    list(options: { prefix: string; limit: number; cursor: string | null }): {
+      keys: { name: string; expiration: number | null; metadata: unknown }[];
+      cursor: string | null;
+    };
+  };
+
+  type KVValueType = "text" | "json" | "arrayBuffer";
+
+  interface KVPutOptions {
+    /** Seconds until the key expires. */
+    expirationTtl?: number;
+    /** Any JSON value, returned along with the value. */
+    metadata?: unknown;
+  }
+
+  interface KVListOptions {
+    prefix?: string;
+    /** At most 1000, the default. */
+    limit?: number;
+    /** Continues a listing, from the `cursor` of the previous page. */
+    cursor?: string;
+  }
+
+  interface KVListResult {
+    keys: { name: string; expiration?: number; metadata?: unknown }[];
+    list_complete: boolean;
+    cursor?: string;
+  }
+
+  /**
+   * A key-value store that outlives single requests, for state such as
+   * feature flags and counters. Its methods mirror Cloudflare Workers KV.
+   */
+  interface KVNamespace {
+    get(key: string, type?: "text"): Promise<string | null>;
+    get<T = unknown>(key: string, type: "json"): Promise<T | null>;
+    get(key: string, type: "arrayBuffer"): Promise<ArrayBuffer | null>;
+    getWithMetadata<M = unknown>(
+      key: string,
+      type?: KVValueType,
+    ): Promise<{ value: any; metadata: M | null }>;
+    put(
+      key: string,
+      value: string | ArrayBuffer | ArrayBufferView,
+      options?: KVPutOptions,
+    ): Promise<void>;
+    delete(key: string): Promise<void>;
+    list(options?: KVListOptions): Promise<KVListResult>;
+  }
+}
+
+const MAX_LIST = 1000;
+
+function decode(bytes: ArrayBuffer, type: KVValueType): unknown {
+  switch (type) {
+    case "arrayBuffer":
+      return bytes;
+    case "json":
+      return JSON.parse(new TextDecoder().decode(bytes));
+    case "text":
+      return new TextDecoder().decode(bytes);
+    default:
+      throw new TypeError(`Unknown KV value type: ${type}`);
+  }
+}
+
+function encode(value: string | ArrayBuffer | ArrayBufferView): ArrayBuffer {
+  if (typeof value === "string") {
+    const bytes = new TextEncoder().encode(value);
+    return bytes.buffer.slice(bytes.byteOffset, bytes.byteOffset + bytes.byteLength);
+  }
+  if (value instanceof ArrayBuffer) {
+    return value;
+  }
+  if (ArrayBuffer.isView(value)) {
+    return value.buffer.slice(value.byteOffset, value.byteOffset + value.byteLength);
+  }
+  throw new TypeError("KV values must be strings, ArrayBuffers or typed arrays");
+}
+
+/** Runs a host call, turning what it throws into a rejected Promise. */
+function call<T>(f: () => T): Promise<T> {
+  return new Promise((resolve) => resolve(f()));
+}
+
+function getWithMetadata(key: string, type: KVValueType = "text") {
+  return call(() => {
+    const entry = __apoxy_kv.get(String(key));
+    if (entry === null) {
+      return { value: null, metadata: null };
+    }
+    return { value: decode(entry.value, type), metadata: entry.metadata };
+  });
+}
+
+const kv: KVNamespace = {
+  get: ((key: string, type: KVValueType = "text") =>
+    getWithMetadata(key, type).then(({ value }) => value)) as KVNamespace["get"],
+
+  getWithMetadata: getWithMetadata as KVNamespace["getWithMetadata"],
+
+  put(key, value, options = {}) {
+    return call(() => {
+      const ttl = options.expirationTtl;
+      if (ttl !== undefined && !(Number.isInteger(ttl) && ttl > 0)) {
+        throw new TypeError("expirationTtl must be a positive number of seconds");
+      }
+      // Metadata travels as its JSON form.
+      const metadata =
+        options.metadata === undefined ? null : JSON.parse(JSON.stringify(options.metadata));
+      __apoxy_kv.put(
+        { key: String(key), expiration_ttl: ttl ?? null, metadata },
+        encode(value),
+      );
+    });
+  },
+
+  delete(key) {
+    return call(() => __apoxy_kv.delete(String(key)));
+  },
+
+  list(options = {}) {
+    return call(() => {
+      const limit = Math.min(Math.max(Math.floor(options.limit ?? MAX_LIST), 1), MAX_LIST);
+      const page = __apoxy_kv.list({
+        prefix: options.prefix ?? "",
+        limit,
+        cursor: options.cursor ?? null,
+      });
+      const result: KVListResult = {
+        keys: page.keys.map(({ name, expiration, metadata }) => ({
+          name,
+          ...(expiration === null ? {} : { expiration }),
+          ...(metadata === null ? {} : { metadata }),
+        })),
+        list_complete: page.cursor === null,
+      };
+      if (page.cursor !== null) {
+        result.cursor = page.cursor;
+      }
+      return result;
+    });
+  },
+};
+
+Apoxy.kv = kv;
+
+export {};
@@ -0,0 +1,98 @@
+import type { HeadersABI, LogLevelABI, LogRecordABI } from "./abi";
+import { format, formatArgs } from "./console";
+import { redact, redactJSON } from "./env";
+
+declare global {
+  /**
+   * Emits a structured log record through the host.
+   *
+   * @internal
+   */
+  function __apoxy_log(record: LogRecordABI): { error: boolean; message: string };
+
+  type LogFields = Record<string, unknown>;
+
+  /**
+   * Writes structured log records, which the proxy emits as JSON along with
+   * the level and the `x-request-id` of the request being handled.
+   */
+  interface Logger {
+    debug(message: string, fields?: LogFields): void;
+    info(message: string, fields?: LogFields): void;
+    warn(message: string, fields?: LogFields): void;
+    error(message: string, fields?: LogFields): void;
+    /** Returns a logger that adds `fields` to every record it writes. */
+    with(fields: LogFields): Logger;
+  }
+}
+
+let requestId: string | null = null;
+
+/**
+ * Sets the request ID attached to records, from the headers of the request
+ * about to be handled.
+ *
+ * @internal
+ */
+export function setRequest(headers: HeadersABI): void {
+  const header = headers.find(([name]) => name.toLowerCase() === "x-request-id");
+  requestId = header === undefined ? null : header[1];
+}
+
+/**
+ * Converts a field value to what its JSON form would be, so the record
+ * survives the trip to the host. Errors keep their name, message and stack,
+ * and values JSON can't hold, such as cycles, are formatted as text.
+ */
+function toField(value: unknown): unknown {
+  try {
+    const json = JSON.stringify(value, (_, x) => {
+      if (typeof x === "bigint") {
+        return x.toString();
+      }
+      if (x instanceof Error) {
+        // Unlike console output, records don't pass through the core's
+        // stack mapping on their way out.
+        const stack = x.stack ? __apoxy_map_stack(x.stack) : "";
+        return { name: x.name, message: x.message, stack };
+      }
+      return x;
+    });
+    return json === undefined ? null : JSON.parse(json);
+  } catch {
+    return format(value);
+  }
+}
+
+function write(level: LogLevelABI, message: unknown, fields: LogFields): void {
+  const record: LogRecordABI = {
+    level,
+    message: redact(typeof message === "string" ? message : format(message)),
+    request_id: requestId,
+    fields: {},
+  };
+  for (const key of Object.keys(fields)) {
+    record.fields[redact(key)] = redactJSON(toField(fields[key]));
+  }
+  const result = __apoxy_log(record);
+  if (result.error === true) {
+    // Don't lose the record if the host refuses it.
+    console[level](formatArgs([record.message, record.fields]));
+  }
+}
+
+function logger(base: LogFields): Logger {
+  const at =
+    (level: LogLevelABI) =>
+    (message: string, fields: LogFields = {}) =>
+      write(level, message, { ...base, ...fields });
+  return {
+    debug: at("debug"),
+    info: at("info"),
+    warn: at("warn"),
+    error: at("error"),
+    with: (fields: LogFields) => logger({ ...base, ...fields }),
+  };
+}
+
+Apoxy.log = logger({});
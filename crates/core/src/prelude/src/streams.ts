@@ -0,0 +1,874 @@
+type ReadRequest<R> = {
+  resolve: (result: ReadableStreamReadResult<R>) => void;
+  reject: (e: any) => void;
+};
+
+type WriteRequest = {
+  chunk: any;
+  resolve: () => void;
+  reject: (e: any) => void;
+};
+
+function deferred<T>(): {
+  promise: Promise<T>;
+  resolve: (value: T) => void;
+  reject: (e: any) => void;
+} {
+  let resolve!: (value: T) => void;
+  let reject!: (e: any) => void;
+  const promise = new Promise<T>((res, rej) => {
+    resolve = res;
+    reject = rej;
+  });
+  // Nobody may be listening, which is not worth an unhandled rejection.
+  promise.catch(() => {});
+  return { promise, resolve, reject };
+}
+
+class ReadableStreamDefaultControllerImpl<R>
+  implements ReadableStreamDefaultController<R>
+{
+  constructor(private _stream: ReadableStreamImpl<R>) {}
+
+  get desiredSize(): number | null {
+    return this._stream._desiredSize();
+  }
+
+  close(): void {
+    this._stream._close();
+  }
+
+  enqueue(chunk: R): void {
+    this._stream._enqueue(chunk);
+  }
+
+  error(e?: any): void {
+    this._stream._error(e);
+  }
+}
+
+/**
+ * A `ReadableStream` with a default (non-byte) controller. Chunks are queued
+ * until read, and `pull` is only called while the queue is below the
+ * high-water mark or a read is waiting.
+ *
+ * @internal
+ */
+export class ReadableStreamImpl<R = any> implements ReadableStream<R> {
+  /** @internal */
+  _state: "readable" | "closed" | "errored" = "readable";
+  /** @internal */
+  _disturbed: boolean = false;
+  /** @internal */
+  _reader: ReadableStreamDefaultReaderImpl<R> | null = null;
+  /** @internal */
+  _storedError: any = undefined;
+
+  private _source: UnderlyingDefaultSource<R>;
+  private _controller: ReadableStreamDefaultControllerImpl<R>;
+  private _highWaterMark: number;
+  private _queue: R[] = [];
+  private _readRequests: ReadRequest<R>[] = [];
+  private _closeRequested: boolean = false;
+  private _started: boolean = false;
+  private _pulling: boolean = false;
+  private _pullAgain: boolean = false;
+
+  constructor(
+    source: UnderlyingDefaultSource<R> = {},
+    strategy: QueuingStrategy<R> = {},
+  ) {
+    if ((source as any).type !== undefined) {
+      throw new RangeError("Byte streams are not supported");
+    }
+    this._source = source;
+    this._highWaterMark = strategy.highWaterMark ?? 1;
+    this._controller = new ReadableStreamDefaultControllerImpl(this);
+
+    Promise.resolve()
+      .then(() => this._source.start?.(this._controller))
+      .then(
+        () => {
+          this._started = true;
+          this._pullIfNeeded();
+        },
+        (e) => this._error(e),
+      );
+  }
+
+  static from<R>(iterable: Iterable<R> | AsyncIterable<R>): ReadableStream<R> {
+    const iterator =
+      (iterable as any)[Symbol.asyncIterator]?.() ??
+      (iterable as any)[Symbol.iterator]();
+    return new ReadableStreamImpl<R>(
+      {
+        pull(controller) {
+          return Promise.resolve(iterator.next()).then((result: any) => {
+            if (result.done) {
+              controller.close();
+            } else {
+              controller.enqueue(result.value);
+            }
+          });
+        },
+        cancel(reason) {
+          return Promise.resolve(iterator.return?.(reason)).then(() => {});
+        },
+      },
+      { highWaterMark: 0 },
+    );
+  }
+
+  get locked(): boolean {
+    return this._reader !== null;
+  }
+
+  cancel(reason?: any): Promise<void> {
+    if (this.locked) {
+      return Promise.reject(new TypeError("ReadableStream is locked"));
+    }
+    return this._cancel(reason);
+  }
+
+  getReader(options?: { mode?: "byob" }): ReadableStreamDefaultReader<R> {
+    if (options?.mode !== undefined) {
+      throw new TypeError("BYOB readers are not supported");
+    }
+    return new ReadableStreamDefaultReaderImpl(this);
+  }
+
+  pipeThrough<T>(
+    transform: ReadableWritablePair<T, R>,
+    options: StreamPipeOptions = {},
+  ): ReadableStream<T> {
+    this.pipeTo(transform.writable, options).catch(() => {});
+    return transform.readable;
+  }
+
+  pipeTo(
+    destination: WritableStream<R>,
+    options: StreamPipeOptions = {},
+  ): Promise<void> {
+    if (this.locked) {
+      return Promise.reject(new TypeError("ReadableStream is locked"));
+    }
+    if (destination.locked) {
+      return Promise.reject(new TypeError("WritableStream is locked"));
+    }
+
+    const reader = this.getReader();
+    const writer = destination.getWriter();
+    const release = () => {
+      reader.releaseLock();
+      writer.releaseLock();
+    };
+
+    const pump = (): Promise<void> =>
+      reader.read().then(
+        ({ value, done }) => {
+          if (done) {
+            return options.preventClose ? undefined : writer.close();
+          }
+          return writer.write(value!).then(pump, (e) => {
+            if (!options.preventCancel) {
+              reader.cancel(e);
+            }
+            throw e;
+          });
+        },
+        (e) => {
+          if (!options.preventAbort) {
+            writer.abort(e);
+          }
+          throw e;
+        },
+      );
+
+    return pump().then(
+      () => release(),
+      (e) => {
+        release();
+        throw e;
+      },
+    );
+  }
+
+  tee(): [ReadableStream<R>, ReadableStream<R>] {
+    const reader = this.getReader();
+    const branches: ReadableStreamDefaultController<R>[] = [];
+    const canceled = [false, false];
+    const reasons: any[] = [undefined, undefined];
+    const cancelDone = deferred<void>();
+    let reading: Promise<void> | null = null;
+
+    const pull = (): Promise<void> => {
+      if (reading === null) {
+        reading = reader.read().then(
+          ({ value, done }) => {
+            reading = null;
+            branches.forEach((controller, i) => {
+              if (canceled[i]) {
+                return;
+              }
+              if (done) {
+                controller.close();
+              } else {
+                controller.enqueue(value!);
+              }
+            });
+          },
+          (e) => {
+            reading = null;
+            branches.forEach((controller) => controller.error(e));
+          },
+        );
+      }
+      return reading;
+    };
+
+    const branch = (i: number) =>
+      new ReadableStreamImpl<R>(
+        {
+          start(controller) {
+            branches[i] = controller;
+          },
+          pull,
+          cancel(reason) {
+            canceled[i] = true;
+            reasons[i] = reason;
+            if (canceled[0] && canceled[1]) {
+              reader.cancel(reasons).then(cancelDone.resolve, cancelDone.reject);
+            }
+            return cancelDone.promise;
+          },
+        },
+        { highWaterMark: 0 },
+      );
+
+    return [branch(0), branch(1)];
+  }
+
+  values(
+    options: { preventCancel?: boolean } = {},
+  ): AsyncIterableIterator<R> {
+    const reader = this.getReader();
+    return {
+      next() {
+        return reader.read().then((result) => {
+          if (result.done) {
+            reader.releaseLock();
+          }
+          return result as IteratorResult<R>;
+        });
+      },
+      return(value?: any) {
+        const done = { value, done: true } as IteratorResult<R>;
+        if (options.preventCancel) {
+          reader.releaseLock();
+          return Promise.resolve(done);
+        }
+        return reader.cancel(value).then(() => {
+          reader.releaseLock();
+          return done;
+        });
+      },
+      [Symbol.asyncIterator]() {
+        return this;
+      },
+    };
+  }
+
+  [Symbol.asyncIterator](): AsyncIterableIterator<R> {
+    return this.values();
+  }
+
+  /** @internal */
+  _desiredSize(): number | null {
+    if (this._state === "errored") {
+      return null;
+    }
+    if (this._state === "closed") {
+      return 0;
+    }
+    return this._highWaterMark - this._queue.length;
+  }
+
+  /**
+   * Whether a reader is waiting or the queue has room, i.e. whether a chunk
+   * enqueued now would not pile up.
+   *
+   * @internal
+   */
+  _wantsData(): boolean {
+    return this._readRequests.length > 0 || this._desiredSize()! > 0;
+  }
+
+  /** @internal */
+  _canCloseOrEnqueue(): boolean {
+    return !this._closeRequested && this._state === "readable";
+  }
+
+  /** @internal */
+  _enqueue(chunk: R): void {
+    if (!this._canCloseOrEnqueue()) {
+      throw new TypeError("ReadableStream is closed");
+    }
+    const request = this._readRequests.shift();
+    if (request !== undefined) {
+      request.resolve({ value: chunk, done: false });
+    } else {
+      this._queue.push(chunk);
+    }
+    this._pullIfNeeded();
+  }
+
+  /** @internal */
+  _close(): void {
+    if (!this._canCloseOrEnqueue()) {
+      throw new TypeError("ReadableStream is closed");
+    }
+    this._closeRequested = true;
+    if (this._queue.length === 0) {
+      this._finishClose();
+    }
+  }
+
+  /** @internal */
+  _error(e: any): void {
+    if (this._state !== "readable") {
+      return;
+    }
+    this._state = "errored";
+    this._storedError = e;
+    this._queue = [];
+    for (const request of this._readRequests.splice(0)) {
+      request.reject(e);
+    }
+    this._reader?._closed.reject(e);
+  }
+
+  /** @internal */
+  _read(): Promise<ReadableStreamReadResult<R>> {
+    this._disturbed = true;
+    if (this._queue.length > 0) {
+      const value = this._queue.shift()!;
+      if (this._closeRequested && this._queue.length === 0) {
+        this._finishClose();
+      } else {
+        this._pullIfNeeded();
+      }
+      return Promise.resolve({ value, done: false });
+    }
+    if (this._state === "closed") {
+      return Promise.resolve({ value: undefined, done: true });
+    }
+    if (this._state === "errored") {
+      return Promise.reject(this._storedError);
+    }
+
+    const { promise, resolve, reject } = deferred<ReadableStreamReadResult<R>>();
+    this._readRequests.push({ resolve, reject });
+    this._pullIfNeeded();
+    return promise;
+  }
+
+  /** @internal */
+  _cancel(reason?: any): Promise<void> {
+    this._disturbed = true;
+    if (this._state === "closed") {
+      return Promise.resolve();
+    }
+    if (this._state === "errored") {
+      return Promise.reject(this._storedError);
+    }
+    this._queue = [];
+    this._finishClose();
+    return Promise.resolve()
+      .then(() => this._source.cancel?.(reason))
+      .then(() => {});
+  }
+
+  /** @internal */
+  _releaseReader(): void {
+    const error = new TypeError("Reader was released");
+    for (const request of this._readRequests.splice(0)) {
+      request.reject(error);
+    }
+    this._reader = null;
+  }
+
+  private _finishClose(): void {
+    this._state = "closed";
+    for (const request of this._readRequests.splice(0)) {
+      request.resolve({ value: undefined, done: true });
+    }
+    this._reader?._closed.resolve(undefined);
+  }
+
+  private _pullIfNeeded(): void {
+    if (
+      !this._started ||
+      this._state !== "readable" ||
+      this._closeRequested ||
+      !this._wantsData()
+    ) {
+      return;
+    }
+    if (this._pulling) {
+      this._pullAgain = true;
+      return;
+    }
+
+    this._pulling = true;
+    Promise.resolve()
+      .then(() => this._source.pull?.(this._controller))
+      .then(
+        () => {
+          this._pulling = false;
+          if (this._pullAgain) {
+            this._pullAgain = false;
+            this._pullIfNeeded();
+          }
+        },
+        (e) => this._error(e),
+      );
+  }
+}
+
+/**
+ * @internal
+ */
+export class ReadableStreamDefaultReaderImpl<R>
+  implements ReadableStreamDefaultReader<R>
+{
+  /** @internal */
+  _closed = deferred<undefined>();
+
+  private _stream: ReadableStreamImpl<R> | null;
+
+  constructor(stream: ReadableStreamImpl<R>) {
+    if (stream.locked) {
+      throw new TypeError("ReadableStream is locked");
+    }
+    this._stream = stream;
+    stream._reader = this;
+    if (stream._state === "closed") {
+      this._closed.resolve(undefined);
+    } else if (stream._state === "errored") {
+      this._closed.reject(stream._storedError);
+    }
+  }
+
+  get closed(): Promise<undefined> {
+    return this._closed.promise;
+  }
+
+  read(): Promise<ReadableStreamReadResult<R>> {
+    if (this._stream === null) {
+      return Promise.reject(new TypeError("Reader was released"));
+    }
+    return this._stream._read();
+  }
+
+  cancel(reason?: any): Promise<void> {
+    if (this._stream === null) {
+      return Promise.reject(new TypeError("Reader was released"));
+    }
+    return this._stream._cancel(reason);
+  }
+
+  releaseLock(): void {
+    if (this._stream === null) {
+      return;
+    }
+    this._stream._releaseReader();
+    this._stream = null;
+    // `closed` now rejects, even if the stream had already closed.
+    this._closed = deferred<undefined>();
+    this._closed.reject(new TypeError("Reader was released"));
+  }
+}
+
+class WritableStreamDefaultControllerImpl
+  implements WritableStreamDefaultController
+{
+  constructor(private _stream: WritableStreamImpl<any>) {}
+
+  error(e?: any): void {
+    this._stream._error(e);
+  }
+}
+
+/**
+ * A `WritableStream` that hands chunks to its sink one at a time, in order.
+ *
+ * @internal
+ */
+export class WritableStreamImpl<W = any> implements WritableStream<W> {
+  /** @internal */
+  _state: "writable" | "closing" | "closed" | "errored" = "writable";
+  /** @internal */
+  _writer: WritableStreamDefaultWriterImpl<W> | null = null;
+  /** @internal */
+  _storedError: any = undefined;
+
+  private _sink: UnderlyingSink<W>;
+  private _controller: WritableStreamDefaultControllerImpl;
+  private _highWaterMark: number;
+  private _queue: WriteRequest[] = [];
+  private _closeRequest: { resolve: () => void; reject: (e: any) => void } | null =
+    null;
+  private _started: boolean = false;
+  private _writing: boolean = false;
+
+  constructor(sink: UnderlyingSink<W> = {}, strategy: QueuingStrategy<W> = {}) {
+    this._sink = sink;
+    this._highWaterMark = strategy.highWaterMark ?? 1;
+    this._controller = new WritableStreamDefaultControllerImpl(this);
+
+    Promise.resolve()
+      .then(() => this._sink.start?.(this._controller))
+      .then(
+        () => {
+          this._started = true;
+          this._advance();
+        },
+        (e) => this._error(e),
+      );
+  }
+
+  get locked(): boolean {
+    return this._writer !== null;
+  }
+
+  abort(reason?: any): Promise<void> {
+    if (this.locked) {
+      return Promise.reject(new TypeError("WritableStream is locked"));
+    }
+    return this._abort(reason);
+  }
+
+  close(): Promise<void> {
+    if (this.locked) {
+      return Promise.reject(new TypeError("WritableStream is locked"));
+    }
+    return this._close();
+  }
+
+  getWriter(): WritableStreamDefaultWriter<W> {
+    return new WritableStreamDefaultWriterImpl(this);
+  }
+
+  /** @internal */
+  _desiredSize(): number | null {
+    if (this._state === "errored" || this._state === "closing") {
+      return this._state === "errored" ? null : 0;
+    }
+    if (this._state === "closed") {
+      return 0;
+    }
+    return this._highWaterMark - this._queue.length - (this._writing ? 1 : 0);
+  }
+
+  /** @internal */
+  _write(chunk: W): Promise<void> {
+    if (this._state === "errored") {
+      return Promise.reject(this._storedError);
+    }
+    if (this._state !== "writable") {
+      return Promise.reject(new TypeError("WritableStream is closed"));
+    }
+    const { promise, resolve, reject } = deferred<void>();
+    this._queue.push({ chunk, resolve, reject });
+    this._writer?._updateReady();
+    this._advance();
+    return promise;
+  }
+
+  /** @internal */
+  _close(): Promise<void> {
+    if (this._state !== "writable") {
+      return Promise.reject(new TypeError("WritableStream is closed"));
+    }
+    this._state = "closing";
+    const { promise, resolve, reject } = deferred<void>();
+    this._closeRequest = { resolve, reject };
+    this._advance();
+    return promise;
+  }
+
+  /** @internal */
+  _abort(reason?: any): Promise<void> {
+    if (this._state === "closed" || this._state === "errored") {
+      return Promise.resolve();
+    }
+    this._error(reason);
+    return Promise.resolve()
+      .then(() => this._sink.abort?.(reason))
+      .then(() => {});
+  }
+
+  /** @internal */
+  _error(e: any): void {
+    if (this._state === "closed" || this._state === "errored") {
+      return;
+    }
+    this._state = "errored";
+    this._storedError = e;
+    for (const request of this._queue.splice(0)) {
+      request.reject(e);
+    }
+    this._closeRequest?.reject(e);
+    this._closeRequest = null;
+    this._writer?._closed.reject(e);
+    this._writer?._ready.reject(e);
+  }
+
+  private _advance(): void {
+    if (!this._started || this._writing || this._state === "errored") {
+      return;
+    }
+
+    const request = this._queue.shift();
+    if (request !== undefined) {
+      this._writing = true;
+      Promise.resolve()
+        .then(() => this._sink.write?.(request.chunk, this._controller))
+        .then(
+          () => {
+            this._writing = false;
+            request.resolve();
+            this._writer?._updateReady();
+            this._advance();
+          },
+          (e) => {
+            this._writing = false;
+            request.reject(e);
+            this._error(e);
+          },
+        );
+      return;
+    }
+
+    if (this._state === "closing" && this._closeRequest !== null) {
+      const closeRequest = this._closeRequest;
+      this._writing = true;
+      Promise.resolve()
+        .then(() => this._sink.close?.())
+        .then(
+          () => {
+            this._writing = false;
+            this._state = "closed";
+            this._closeRequest = null;
+            closeRequest.resolve();
+            this._writer?._closed.resolve(undefined);
+          },
+          (e) => {
+            this._writing = false;
+            this._error(e);
+          },
+        );
+    }
+  }
+}
+
+/**
+ * @internal
+ */
+export class WritableStreamDefaultWriterImpl<W>
+  implements WritableStreamDefaultWriter<W>
+{
+  /** @internal */
+  _closed = deferred<undefined>();
+  /** @internal */
+  _ready = deferred<undefined>();
+
+  private _stream: WritableStreamImpl<W> | null;
+
+  constructor(stream: WritableStreamImpl<W>) {
+    if (stream.locked) {
+      throw new TypeError("WritableStream is locked");
+    }
+    this._stream = stream;
+    stream._writer = this;
+    if (stream._state === "closed") {
+      this._closed.resolve(undefined);
+    } else if (stream._state === "errored") {
+      this._closed.reject(stream._storedError);
+      this._ready.reject(stream._storedError);
+      return;
+    }
+    this._updateReady();
+  }
+
+  get closed(): Promise<undefined> {
+    return this._closed.promise;
+  }
+
+  get ready(): Promise<undefined> {
+    return this._ready.promise;
+  }
+
+  get desiredSize(): number | null {
+    if (this._stream === null) {
+      throw new TypeError("Writer was released");
+    }
+    return this._stream._desiredSize();
+  }
+
+  abort(reason?: any): Promise<void> {
+    if (this._stream === null) {
+      return Promise.reject(new TypeError("Writer was released"));
+    }
+    return this._stream._abort(reason);
+  }
+
+  close(): Promise<void> {
+    if (this._stream === null) {
+      return Promise.reject(new TypeError("Writer was released"));
+    }
+    return this._stream._close();
+  }
+
+  write(chunk: W): Promise<void> {
+    if (this._stream === null) {
+      return Promise.reject(new TypeError("Writer was released"));
+    }
+    return this._stream._write(chunk);
+  }
+
+  releaseLock(): void {
+    if (this._stream === null) {
+      return;
+    }
+    this._stream._writer = null;
+    this._stream = null;
+    const error = new TypeError("Writer was released");
+    this._closed = deferred<undefined>();
+    this._closed.reject(error);
+    this._ready = deferred<undefined>();
+    this._ready.reject(error);
+  }
+
+  /**
+   * Resolves `ready` while the stream has room and replaces it with a pending
+   * promise once it fills up.
+   *
+   * @internal
+   */
+  _updateReady(): void {
+    const size = this._stream?._desiredSize() ?? null;
+    if (size !== null && size > 0) {
+      this._ready.resolve(undefined);
+    } else if (size !== null) {
+      this._ready = deferred<undefined>();
+    }
+  }
+}
+
+class TransformStreamDefaultControllerImpl<O>
+  implements TransformStreamDefaultController<O>
+{
+  constructor(
+    private _readable: ReadableStreamImpl<O>,
+    private _writable: WritableStreamImpl<any>,
+  ) {}
+
+  get desiredSize(): number | null {
+    return this._readable._desiredSize();
+  }
+
+  enqueue(chunk: O): void {
+    this._readable._enqueue(chunk);
+  }
+
+  error(reason?: any): void {
+    this._readable._error(reason);
+    this._writable._error(reason);
+  }
+
+  terminate(): void {
+    if (this._readable._canCloseOrEnqueue()) {
+      this._readable._close();
+    }
+    this._writable._error(new TypeError("TransformStream was terminated"));
+  }
+}
+
+/**
+ * A `TransformStream`. Writes wait until the readable side is being read, so
+ * a transform in the middle of a pipe never buffers more than one chunk.
+ *
+ * @internal
+ */
+export class TransformStreamImpl<I = any, O = any>
+  implements TransformStream<I, O>
+{
+  readonly readable: ReadableStream<O>;
+  readonly writable: WritableStream<I>;
+
+  constructor(
+    transformer: Transformer<I, O> = {},
+    writableStrategy: QueuingStrategy<I> = {},
+    readableStrategy: QueuingStrategy<O> = { highWaterMark: 0 },
+  ) {
+    let waiting: (() => void) | null = null;
+    const readable = new ReadableStreamImpl<O>(
+      {
+        pull() {
+          const resume = waiting;
+          waiting = null;
+          resume?.();
+        },
+        cancel(reason) {
+          writable._error(reason);
+          return transformer.cancel?.(reason);
+        },
+      },
+      readableStrategy,
+    );
+    const whenWanted = (): Promise<void> =>
+      readable._wantsData()
+        ? Promise.resolve()
+        : new Promise((resolve) => {
+            waiting = resolve;
+          });
+
+    const writable: WritableStreamImpl<I> = new WritableStreamImpl<I>(
+      {
+        start() {
+          return transformer.start?.(controller);
+        },
+        write(chunk) {
+          return whenWanted().then(() =>
+            transformer.transform
+              ? transformer.transform(chunk, controller)
+              : controller.enqueue(chunk as unknown as O),
+          );
+        },
+        close() {
+          return Promise.resolve(transformer.flush?.(controller)).then(() => {
+            if (readable._canCloseOrEnqueue()) {
+              readable._close();
+            }
+          });
+        },
+        abort(reason) {
+          readable._error(reason);
+        },
+      },
+      writableStrategy,
+    );
+    const controller = new TransformStreamDefaultControllerImpl<O>(
+      readable,
+      writable,
+    );
+
+    this.readable = readable;
+    this.writable = writable;
+  }
+}
+
+globalThis.ReadableStream = ReadableStreamImpl as any;
+globalThis.WritableStream = WritableStreamImpl as any;
+globalThis.TransformStream = TransformStreamImpl as any;
@@ -0,0 +1,122 @@
+import { dispatch, isServed } from "./apoxy";
+import type { RequestABI } from "./abi";
+import { ResponseImpl } from "./http";
+
+declare global {
+  /**
+   * @internal
+   */
+  var module: { exports: any };
+
+  /**
+   * Names how the user's code registered its handler: `"phases"` for
+   * `Apoxy.onRequest` and friends, `"serve"`, `"default"` for a default
+   * export with `fetch`, `"exports"` for a `fetch` export, or null. Checked
+   * after evaluation when the module is built.
+   *
+   * @internal
+   */
+  var __apoxy_handler_style: () => string | null;
+
+  interface ExecutionContext {
+    waitUntil(promise: Promise<any>): void;
+
+    passThroughOnException(): void;
+  }
+
+  /**
+   * A Cloudflare Workers style handler, exported as `export default { fetch }`.
+   * It is only used when the script does not call `Apoxy.serve`.
+   */
+  interface ExportedHandler<Env = Record<string, string | null>> {
+    fetch(
+      request: ApoxyRequest,
+      env: Env,
+      ctx: ExecutionContext,
+    ): Response | Promise<Response>;
+  }
+}
+
+class ExecutionContextImpl implements ExecutionContext {
+  passThrough: boolean = false;
+
+  waitUntil(promise: Promise<any>): void {
+    // Pending jobs are drained before `_apoxy_start` returns, so there is
+    // nothing to wait for beyond surfacing failures.
+    Promise.resolve(promise).catch((e) => {
+      console.error("[apoxy/js] Exception in waitUntil:", e);
+    });
+  }
+
+  passThroughOnException(): void {
+    this.passThrough = true;
+  }
+}
+
+const workerEnv = new Proxy({} as Record<string, string | null>, {
+  get(_target, key) {
+    return typeof key === "string" ? Apoxy.env.get(key) : undefined;
+  },
+  has(_target, key) {
+    return typeof key === "string" && Apoxy.env.get(key) !== null;
+  },
+  ownKeys() {
+    return Apoxy.env.keys();
+  },
+  getOwnPropertyDescriptor(_target, key) {
+    const value = typeof key === "string" ? Apoxy.env.get(key) : null;
+    return value === null
+      ? undefined
+      : { value, enumerable: true, configurable: true, writable: false };
+  },
+});
+
+function exportedHandler(): ExportedHandler | null {
+  const exports = globalThis.module?.exports;
+  const candidate = exports?.default ?? exports;
+  if (candidate && typeof candidate.fetch === "function") {
+    return candidate;
+  }
+  return null;
+}
+
+__apoxy_handler_style = () => {
+  if (__apoxy_phases() !== "") {
+    return "phases";
+  }
+  if (isServed()) {
+    return "serve";
+  }
+  const exports = globalThis.module?.exports;
+  if (typeof exports?.default?.fetch === "function") {
+    return "default";
+  }
+  if (typeof exports?.fetch === "function") {
+    return "exports";
+  }
+  return null;
+};
+
+__handler = (reqABI: RequestABI) => {
+  const handler = exportedHandler();
+  const ctx = new ExecutionContextImpl();
+  dispatch(
+    reqABI,
+    (req) => {
+      if (!handler) {
+        throw new Error(
+          "No handler registered. Call Apoxy.serve() or export default { fetch }.",
+        );
+      }
+      return Promise.resolve(handler.fetch(req, workerEnv, ctx)).then((response) => {
+        if (!(response instanceof ResponseImpl)) {
+          throw new TypeError("The fetch handler must return a Response");
+        }
+        return response;
+      });
+    },
+    () => ctx.passThrough,
+  );
+};
+
+export {};
@@ -0,0 +1,137 @@
+use quickjs_wasm_rs::quickjs_wasm_sys::{
+    JS_EvalFunction, JS_GetException, JS_ReadObject, JS_ResolveModule, JS_READ_OBJ_BYTECODE,
+};
+use quickjs_wasm_rs::{Exception, JSContextRef, JSValueRef};
+use serde::{Deserialize, Serialize};
+
+use crate::sourcemap::SourceMap;
+
+/// The user's code as written by the CLI for `wizer.initialize`, see
+/// `crates/cli/src/source.rs`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    pub kind: SourceKind,
+    pub modules: Vec<SourceModule>,
+    /// Evaluate the code in `wizer.initialize` rather than in `_start`.
+    pub initialize: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum SourceKind {
+    Script,
+    Module,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SourceModule {
+    pub name: String,
+    pub code: Code,
+    #[serde(default)]
+    pub source_map: Option<SourceMap>,
+}
+
+/// A module's code, as text or as QuickJS bytecode from `_apoxy_compile`.
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Code {
+    Text(String),
+    Bytecode(Vec<u8>),
+}
+
+impl Source {
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec(self)?)
+    }
+
+    fn entry(&self) -> anyhow::Result<&SourceModule> {
+        self.modules
+            .last()
+            .ok_or_else(|| anyhow::anyhow!("[core] No user code was provided"))
+    }
+
+    /// Replaces the text of every module with bytecode, so the source isn't
+    /// shipped and doesn't have to be parsed again.
+    pub fn compile(&mut self, context: &JSContextRef) -> anyhow::Result<()> {
+        for module in &mut self.modules {
+            let Code::Text(text) = &module.code else {
+                continue;
+            };
+            let bytecode = match self.kind {
+                SourceKind::Script => context.compile_global(&module.name, text)?,
+                SourceKind::Module => context.compile_module(&module.name, text)?,
+            };
+            module.code = Code::Bytecode(bytecode);
+        }
+        Ok(())
+    }
+
+    /// Evaluates the user's code. ES modules are evaluated dependencies first,
+    /// so each import resolves to an already loaded module, and the entry
+    /// point's namespace is exposed as `module.exports`.
+    pub fn eval(&self, context: &JSContextRef) -> anyhow::Result<()> {
+        match self.kind {
+            SourceKind::Script => {
+                let entry = self.entry()?;
+                match &entry.code {
+                    Code::Text(text) => context.eval_global(&entry.name, text)?,
+                    Code::Bytecode(bytecode) => context.eval_binary(bytecode)?,
+                };
+            }
+            SourceKind::Module => {
+                for module in &self.modules {
+                    match &module.code {
+                        Code::Text(text) => {
+                            context.eval_module(&module.name, text)?;
+                        }
+                        Code::Bytecode(bytecode) => eval_module_bytecode(context, bytecode)?,
+                    }
+                }
+                context.eval_module(
+                    "__apoxy_entry.js",
+                    &format!(
+                        "import * as exports from {:?}; globalThis.module.exports = exports;",
+                        self.entry()?.name
+                    ),
+                )?;
+            }
+        }
+
+        // Settle top-level await.
+        crate::run_pending(context)?;
+
+        Ok(())
+    }
+}
+
+/// A module read from bytecode only knows the names of its imports, so unlike
+/// `eval_binary` this resolves them against the modules loaded before it.
+fn eval_module_bytecode(context: &JSContextRef, bytecode: &[u8]) -> anyhow::Result<()> {
+    unsafe {
+        let ctx = context.as_raw();
+        let module = JS_ReadObject(
+            ctx,
+            bytecode.as_ptr(),
+            bytecode.len() as _,
+            JS_READ_OBJ_BYTECODE as i32,
+        );
+        if JSValueRef::from_raw(context, module).is_exception() || JS_ResolveModule(ctx, module) < 0
+        {
+            return Err(exception(context));
+        }
+        if JSValueRef::from_raw(context, JS_EvalFunction(ctx, module)).is_exception() {
+            return Err(exception(context));
+        }
+    }
+    Ok(())
+}
+
+unsafe fn exception(context: &JSContextRef) -> anyhow::Error {
+    let value = JSValueRef::from_raw(context, JS_GetException(context.as_raw()));
+    match Exception::from(value) {
+        Ok(exception) => exception.into_error(),
+        Err(e) => e,
+    }
+}
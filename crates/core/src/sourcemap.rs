@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use crate::source::Source;
+
+/// A decoded source map as written by the CLI, see
+/// `crates/cli/src/sourcemap.rs`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SourceMap {
+    pub sources: Vec<String>,
+    pub lines: Vec<Vec<Segment>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Segment {
+    pub column: u32,
+    pub source: u32,
+    pub line: u32,
+    pub source_column: u32,
+}
+
+impl SourceMap {
+    /// Looks up a 1-based generated position and returns the 1-based original
+    /// one. QuickJS frames often carry no column, in which case the first
+    /// mapping on the line is used.
+    fn lookup(&self, line: u32, column: Option<u32>) -> Option<(&str, u32, u32)> {
+        let segments = self.lines.get(line.checked_sub(1)? as usize)?;
+        let segment = match column {
+            Some(column) => segments
+                .iter()
+                .rev()
+                .find(|x| x.column < column)
+                .or(segments.first())?,
+            None => segments.first()?,
+        };
+        let source = self.sources.get(segment.source as usize)?;
+        Some((source, segment.line + 1, segment.source_column + 1))
+    }
+}
+
+/// Rewrites the `(file:line)` and `(file:line:column)` locations of a stack
+/// trace that point into a module with a source map. Other frames are left as
+/// they are, so mapping a trace twice is harmless.
+pub fn map_stack(source: &Source, stack: &str) -> String {
+    stack
+        .split_inclusive('\n')
+        .map(|frame| map_frame(source, frame).unwrap_or_else(|| frame.to_string()))
+        .collect()
+}
+
+fn map_frame(source: &Source, frame: &str) -> Option<String> {
+    let open = frame.rfind('(')?;
+    let close = open + frame[open..].find(')')?;
+    let location = &frame[open + 1..close];
+
+    let (rest, last) = location.rsplit_once(':')?;
+    let last: u32 = last.parse().ok()?;
+    let (file, line, column) = match rest
+        .rsplit_once(':')
+        .and_then(|(file, line)| Some((file, line.parse::<u32>().ok()?)))
+    {
+        Some((file, line)) => (file, line, Some(last)),
+        None => (rest, last, None),
+    };
+
+    let map = source
+        .modules
+        .iter()
+        .find(|x| x.name == file)?
+        .source_map
+        .as_ref()?;
+    let (file, line, column) = map.lookup(line, column)?;
+    Some(format!(
+        "{}({}:{}:{}){}",
+        &frame[..open],
+        file,
+        line,
+        column,
+        &frame[close + 1..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::PRELUDE_NAME;
+    use crate::source::{Code, SourceKind, SourceModule};
+
+    /// A script whose first line came from line 10 of `src/index.ts`.
+    fn source() -> Source {
+        Source {
+            kind: SourceKind::Script,
+            modules: vec![SourceModule {
+                name: "script.js".to_string(),
+                code: Code::Text(String::new()),
+                source_map: Some(SourceMap {
+                    sources: vec!["src/index.ts".to_string()],
+                    lines: vec![vec![Segment {
+                        column: 0,
+                        source: 0,
+                        line: 9,
+                        source_column: 2,
+                    }]],
+                }),
+            }],
+            initialize: true,
+        }
+    }
+
+    #[test]
+    fn maps_frames_in_user_code() {
+        let stack = "    at handler (script.js:1:5)\n";
+        assert_eq!(
+            map_stack(&source(), stack),
+            "    at handler (src/index.ts:10:3)\n"
+        );
+    }
+
+    #[test]
+    fn leaves_prelude_frames_alone() {
+        let stack = format!(
+            "    at handler (script.js:1)\n    at dispatch ({}:1)\n    at <eval> ({}:1:7)\n",
+            PRELUDE_NAME, PRELUDE_NAME
+        );
+        assert_eq!(
+            map_stack(&source(), &stack),
+            format!(
+                "    at handler (src/index.ts:10:3)\n    at dispatch ({}:1)\n    at <eval> ({}:1:7)\n",
+                PRELUDE_NAME, PRELUDE_NAME
+            )
+        );
+    }
+}
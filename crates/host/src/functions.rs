@@ -0,0 +1,570 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use extism::{CurrentPlugin, Error, Function, UserData, Val, PTR};
+
+use js_abi::{
+    FetchRequest, FetchResponse, KvList, KvPut, KvValue, LogRecord, RequestAbi, ResponseAbi,
+};
+
+use crate::kv::{Kv, KvEntry, MemoryKv};
+use crate::message::{HttpRequest, HttpResponse};
+use crate::upstream::Upstream;
+
+/// Per-invocation state shared by the `extism:host/user` imports.
+pub(crate) struct State {
+    pub request: HttpRequest,
+    pub upstream: Arc<dyn Upstream>,
+    pub upstream_response: Option<HttpResponse>,
+    pub modified_response: Option<HttpResponse>,
+    pub downstream: Option<HttpResponse>,
+    pub fetches: Fetches,
+    /// The config keys, listed by `_apoxy_env_keys`.
+    pub env_keys: Vec<String>,
+    /// The values behind `Apoxy.secrets`, kept apart from the config.
+    pub secrets: BTreeMap<String, String>,
+    pub kv: Arc<dyn Kv>,
+    /// How much of the request and upstream response bodies has been read.
+    request_read: usize,
+    response_read: usize,
+    /// Where `_apoxy_resp_write` appends, after `_apoxy_resp_stream`.
+    streaming: Option<Stream>,
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Modified,
+    Downstream,
+}
+
+impl State {
+    pub fn new(upstream: Arc<dyn Upstream>) -> Self {
+        Self {
+            request: HttpRequest::default(),
+            upstream,
+            upstream_response: None,
+            modified_response: None,
+            downstream: None,
+            fetches: Fetches::new(),
+            env_keys: Vec::new(),
+            secrets: BTreeMap::new(),
+            kv: Arc::new(MemoryKv::new()),
+            request_read: 0,
+            response_read: 0,
+            streaming: None,
+        }
+    }
+
+    pub fn reset(&mut self, request: HttpRequest) {
+        self.request = request;
+        self.upstream_response = None;
+        self.modified_response = None;
+        self.downstream = None;
+        self.fetches = Fetches::new();
+        self.request_read = 0;
+        self.response_read = 0;
+        self.streaming = None;
+    }
+
+    fn stream_target(&mut self) -> Option<&mut HttpResponse> {
+        match self.streaming? {
+            Stream::Modified => self.modified_response.as_mut(),
+            Stream::Downstream => self.downstream.as_mut(),
+        }
+    }
+}
+
+type Completion = (u64, anyhow::Result<HttpResponse>);
+
+/// Outbound `fetch` calls started by `_apoxy_fetch_start` and not yet
+/// returned by `_apoxy_fetch_wait`.
+pub(crate) struct Fetches {
+    next_handle: u64,
+    in_flight: usize,
+    tx: Sender<Completion>,
+    rx: Receiver<Completion>,
+}
+
+impl Fetches {
+    fn new() -> Self {
+        let (tx, rx) = channel();
+        Self {
+            next_handle: 1,
+            in_flight: 0,
+            tx,
+            rx,
+        }
+    }
+}
+
+pub(crate) fn all(state: &UserData<State>) -> Vec<Function> {
+    vec![
+        Function::new(
+            "_apoxy_req_body_read",
+            [PTR],
+            [PTR],
+            state.clone(),
+            req_body_read,
+        ),
+        Function::new(
+            "_apoxy_req_send",
+            [PTR, PTR],
+            [PTR],
+            state.clone(),
+            req_send,
+        ),
+        Function::new("_apoxy_req_set", [PTR, PTR], [PTR], state.clone(), req_set),
+        Function::new(
+            "_apoxy_resp_body_read",
+            [PTR],
+            [PTR],
+            state.clone(),
+            resp_body_read,
+        ),
+        Function::new(
+            "_apoxy_resp_send",
+            [PTR, PTR],
+            [PTR],
+            state.clone(),
+            resp_send,
+        ),
+        Function::new(
+            "_apoxy_send_downstream",
+            [PTR, PTR],
+            [PTR],
+            state.clone(),
+            send_downstream,
+        ),
+        Function::new(
+            "_apoxy_resp_stream",
+            [PTR, PTR],
+            [PTR],
+            state.clone(),
+            resp_stream,
+        ),
+        Function::new("_apoxy_resp_write", [PTR], [PTR], state.clone(), resp_write),
+        Function::new("_apoxy_resp_close", [], [PTR], state.clone(), resp_close),
+        Function::new(
+            "_apoxy_fetch_start",
+            [PTR, PTR],
+            [PTR],
+            state.clone(),
+            fetch_start,
+        ),
+        Function::new("_apoxy_fetch_wait", [], [PTR], state.clone(), fetch_wait),
+        Function::new("_apoxy_log", [PTR], [PTR], state.clone(), log),
+        Function::new("_apoxy_env_keys", [], [PTR], state.clone(), env_keys),
+        Function::new("_apoxy_secret_get", [PTR], [PTR], state.clone(), secret_get),
+        Function::new("_apoxy_kv_get", [PTR], [PTR], state.clone(), kv_get),
+        Function::new("_apoxy_kv_put", [PTR, PTR], [PTR], state.clone(), kv_put),
+        Function::new("_apoxy_kv_delete", [PTR], [PTR], state.clone(), kv_delete),
+        Function::new("_apoxy_kv_list", [PTR], [PTR], state.clone(), kv_list),
+    ]
+}
+
+fn read(plugin: &mut CurrentPlugin, val: &Val) -> Result<Vec<u8>, Error> {
+    match plugin.memory_from_val(val) {
+        Some(handle) => Ok(plugin.memory_bytes(handle)?.to_vec()),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn code(rc: u64) -> Val {
+    Val::I64(rc as i64)
+}
+
+fn write(plugin: &mut CurrentPlugin, bytes: &[u8]) -> Result<Val, Error> {
+    let handle = plugin.memory_new(bytes)?;
+    Ok(plugin.memory_to_val(handle))
+}
+
+/// Returns the next chunk of `body` after `pos`, or 0 once it is exhausted.
+fn read_chunk(
+    plugin: &mut CurrentPlugin,
+    body: &[u8],
+    pos: &mut usize,
+    max: &Val,
+) -> Result<Val, Error> {
+    if *pos >= body.len() {
+        return Ok(code(js_abi::NONE));
+    }
+    let max = max.unwrap_i64().max(1) as usize;
+    let end = body.len().min(*pos + max);
+    let chunk = write(plugin, &body[*pos..end])?;
+    *pos = end;
+    Ok(chunk)
+}
+
+fn req_body_read(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    let State {
+        request,
+        request_read,
+        ..
+    } = &mut *state;
+    outputs[0] = read_chunk(plugin, &request.body, request_read, &inputs[0])?;
+    Ok(())
+}
+
+fn req_send(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let abi: RequestAbi = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let mut body = read(plugin, &inputs[1])?;
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    // An empty body means the handler did not replace it.
+    if body.is_empty() {
+        body = state.request.body.clone();
+    }
+    let resp = state.upstream.send(&HttpRequest::from_abi(abi, body))?;
+    outputs[0] = write(plugin, &js_abi::encode(&resp.to_abi())?)?;
+    state.upstream_response = Some(resp);
+    state.response_read = 0;
+    Ok(())
+}
+
+fn req_set(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let abi: RequestAbi = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let mut body = read(plugin, &inputs[1])?;
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    // An empty body means the handler did not replace it.
+    if body.is_empty() {
+        body = state.request.body.clone();
+    }
+    state.request = HttpRequest::from_abi(abi, body);
+    outputs[0] = code(js_abi::OK);
+    Ok(())
+}
+
+fn resp_body_read(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    let State {
+        upstream_response,
+        response_read,
+        ..
+    } = &mut *state;
+    let body = upstream_response
+        .as_ref()
+        .map(|x| x.body.as_slice())
+        .unwrap_or_default();
+    outputs[0] = read_chunk(plugin, body, response_read, &inputs[0])?;
+    Ok(())
+}
+
+fn resp_send(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let abi: ResponseAbi = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let mut body = read(plugin, &inputs[1])?;
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    // The response is modified in place, so an empty body keeps the upstream one.
+    if body.is_empty() {
+        if let Some(upstream) = &state.upstream_response {
+            body = upstream.body.clone();
+        }
+    }
+    state.modified_response = Some(HttpResponse::from_abi(abi, body));
+    outputs[0] = code(js_abi::OK);
+    Ok(())
+}
+
+fn send_downstream(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let abi: ResponseAbi = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let body = read(plugin, &inputs[1])?;
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    state.downstream = Some(HttpResponse::from_abi(abi, body));
+    outputs[0] = code(js_abi::OK);
+    Ok(())
+}
+
+fn resp_stream(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let abi: ResponseAbi = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let downstream = inputs[1].unwrap_i64() != 0;
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    let resp = Some(HttpResponse::from_abi(abi, Vec::new()));
+    if downstream {
+        state.downstream = resp;
+        state.streaming = Some(Stream::Downstream);
+    } else {
+        state.modified_response = resp;
+        state.streaming = Some(Stream::Modified);
+    }
+    outputs[0] = code(js_abi::OK);
+    Ok(())
+}
+
+fn resp_write(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let chunk = read(plugin, &inputs[0])?;
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    outputs[0] = match state.stream_target() {
+        Some(resp) => {
+            resp.body.extend_from_slice(&chunk);
+            code(js_abi::OK)
+        }
+        None => code(js_abi::FAILED),
+    };
+    Ok(())
+}
+
+fn resp_close(
+    _plugin: &mut CurrentPlugin,
+    _inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    outputs[0] = match state.streaming.take() {
+        Some(_) => code(js_abi::OK),
+        None => code(js_abi::FAILED),
+    };
+    Ok(())
+}
+
+/// Prints a structured log record as a line of JSON, the form the proxy
+/// hands its log pipeline.
+fn log(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    _state: UserData<State>,
+) -> Result<(), Error> {
+    let record: LogRecord = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    if crate::log_enabled(record.level) {
+        eprintln!("{}", serde_json::to_string(&record)?);
+    }
+    outputs[0] = code(js_abi::OK);
+    Ok(())
+}
+
+fn env_keys(
+    plugin: &mut CurrentPlugin,
+    _inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let state = state.get()?;
+    let state = state.lock().unwrap();
+    outputs[0] = write(plugin, &js_abi::encode(&state.env_keys)?)?;
+    Ok(())
+}
+
+fn secret_get(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let key = String::from_utf8(read(plugin, &inputs[0])?)?;
+
+    let state = state.get()?;
+    let state = state.lock().unwrap();
+    outputs[0] = match state.secrets.get(&key) {
+        Some(value) => write(plugin, value.as_bytes())?,
+        None => code(js_abi::NONE),
+    };
+    Ok(())
+}
+
+fn kv_get(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let key = String::from_utf8(read(plugin, &inputs[0])?)?;
+
+    let kv = state.get()?.lock().unwrap().kv.clone();
+    outputs[0] = match kv.get(&key)? {
+        Some(entry) => {
+            let value = plugin.memory_new(&entry.value)?;
+            let resp = KvValue {
+                value_offset: value.offset(),
+                metadata: entry.metadata,
+            };
+            write(plugin, &js_abi::encode(&resp)?)?
+        }
+        None => code(js_abi::NONE),
+    };
+    Ok(())
+}
+
+fn kv_put(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let put: KvPut = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let value = read(plugin, &inputs[1])?;
+
+    let kv = state.get()?.lock().unwrap().kv.clone();
+    let entry = KvEntry {
+        value,
+        expiration: put.expiration_ttl.map(|x| crate::kv::now() + x),
+        metadata: put.metadata,
+    };
+    outputs[0] = match kv.put(&put.key, entry) {
+        Ok(()) => code(js_abi::OK),
+        Err(_) => code(js_abi::FAILED),
+    };
+    Ok(())
+}
+
+fn kv_delete(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let key = String::from_utf8(read(plugin, &inputs[0])?)?;
+
+    let kv = state.get()?.lock().unwrap().kv.clone();
+    outputs[0] = match kv.delete(&key) {
+        Ok(()) => code(js_abi::OK),
+        Err(_) => code(js_abi::FAILED),
+    };
+    Ok(())
+}
+
+fn kv_list(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let query: KvList = js_abi::decode(&read(plugin, &inputs[0])?)?;
+
+    let kv = state.get()?.lock().unwrap().kv.clone();
+    outputs[0] = match kv.list(&query) {
+        Ok(result) => write(plugin, &js_abi::encode(&result)?)?,
+        Err(_) => code(js_abi::NONE),
+    };
+    Ok(())
+}
+
+fn fetch_start(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let req: FetchRequest = js_abi::decode(&read(plugin, &inputs[0])?)?;
+    let body = read(plugin, &inputs[1])?;
+    let req = HttpRequest {
+        method: req.method,
+        url: req.url,
+        headers: req.headers,
+        body,
+        ..HttpRequest::default()
+    };
+
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    let handle = state.fetches.next_handle;
+    state.fetches.next_handle += 1;
+    state.fetches.in_flight += 1;
+
+    let upstream = state.upstream.clone();
+    let tx = state.fetches.tx.clone();
+    thread::spawn(move || {
+        // The receiver is gone if the invocation already finished.
+        let _ = tx.send((handle, upstream.fetch(&req)));
+    });
+
+    outputs[0] = Val::I64(handle as i64);
+    Ok(())
+}
+
+/// Blocks until an in-flight fetch completes and returns its response, or
+/// returns 0 straight away when nothing is in flight.
+fn fetch_wait(
+    plugin: &mut CurrentPlugin,
+    _inputs: &[Val],
+    outputs: &mut [Val],
+    state: UserData<State>,
+) -> Result<(), Error> {
+    let state = state.get()?;
+    let mut state = state.lock().unwrap();
+    if state.fetches.in_flight == 0 {
+        outputs[0] = code(js_abi::NONE);
+        return Ok(());
+    }
+    let (handle, result) = state.fetches.rx.recv()?;
+    state.fetches.in_flight -= 1;
+
+    let resp = match result {
+        Ok(resp) => {
+            let body = plugin.memory_new(&resp.body)?;
+            FetchResponse {
+                handle,
+                status: resp.status,
+                headers: resp.headers,
+                body_offset: body.offset(),
+                error: None,
+            }
+        }
+        Err(e) => FetchResponse {
+            handle,
+            status: 0,
+            headers: Default::default(),
+            body_offset: 0,
+            error: Some(e.to_string()),
+        },
+    };
+    let bytes = js_abi::encode(&resp)?;
+    outputs[0] = write(plugin, &bytes)?;
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use js_abi::{KvKey, KvList, KvListResult};
+
+/// A stored value along with what `Apoxy.kv.put` attached to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KvEntry {
+    pub value: Vec<u8>,
+    /// When the key expires, in seconds since the Unix epoch.
+    pub expiration: Option<u64>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl KvEntry {
+    fn expired(&self, now: u64) -> bool {
+        self.expiration.is_some_and(|x| x <= now)
+    }
+}
+
+/// The store behind `Apoxy.kv`. It outlives single requests, so handlers can
+/// keep state such as feature flags and counters across them.
+pub trait Kv: Send + Sync {
+    /// Returns the entry for `key`, unless it is missing or expired.
+    fn get(&self, key: &str) -> Result<Option<KvEntry>>;
+
+    fn put(&self, key: &str, entry: KvEntry) -> Result<()>;
+
+    fn delete(&self, key: &str) -> Result<()>;
+
+    /// Lists the live keys matching the query, in order.
+    fn list(&self, query: &KvList) -> Result<KvListResult>;
+}
+
+/// Seconds since the Unix epoch, the unit of `KvEntry::expiration`.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+/// A store that keeps everything in memory, for tests and `apoxy-js run`.
+#[derive(Debug, Default)]
+pub struct MemoryKv {
+    entries: Mutex<BTreeMap<String, KvEntry>>,
+}
+
+impl MemoryKv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Kv for MemoryKv {
+    fn get(&self, key: &str) -> Result<Option<KvEntry>> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(key).is_some_and(|x| x.expired(now())) {
+            entries.remove(key);
+        }
+        Ok(entries.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, entry: KvEntry) -> Result<()> {
+        self.entries.lock().unwrap().insert(key.to_string(), entry);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn list(&self, query: &KvList) -> Result<KvListResult> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = now();
+        entries.retain(|_, x| !x.expired(now));
+
+        // A cursor that sorts before the prefix would stop the listing at the
+        // first key in between.
+        let start = match &query.cursor {
+            Some(cursor) if *cursor >= query.prefix => Bound::Excluded(cursor.clone()),
+            _ => Bound::Included(query.prefix.clone()),
+        };
+        let mut matching = entries
+            .range((start, Bound::Unbounded))
+            .take_while(|(name, _)| name.starts_with(&query.prefix));
+        let keys = matching
+            .by_ref()
+            .take(query.limit as usize)
+            .map(|(name, entry)| KvKey {
+                name: name.clone(),
+                expiration: entry.expiration,
+                metadata: entry.metadata.clone(),
+            })
+            .collect::<Vec<_>>();
+        let cursor = match matching.next() {
+            Some(_) => keys.last().map(|x| x.name.clone()),
+            None => None,
+        };
+        Ok(KvListResult { keys, cursor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(keys: &[&str]) -> MemoryKv {
+        let kv = MemoryKv::new();
+        for key in keys {
+            kv.put(key, KvEntry::default()).unwrap();
+        }
+        kv
+    }
+
+    fn list(kv: &MemoryKv, prefix: &str, limit: u32, cursor: Option<&str>) -> KvListResult {
+        kv.list(&KvList {
+            prefix: prefix.to_string(),
+            limit,
+            cursor: cursor.map(str::to_string),
+        })
+        .unwrap()
+    }
+
+    fn names(result: &KvListResult) -> Vec<&str> {
+        result.keys.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    #[test]
+    fn expired_entries_are_gone() {
+        let kv = MemoryKv::new();
+        let expired = KvEntry {
+            value: b"old".to_vec(),
+            expiration: Some(now() - 1),
+            ..KvEntry::default()
+        };
+        let live = KvEntry {
+            value: b"new".to_vec(),
+            expiration: Some(now() + 60),
+            metadata: Some(serde_json::json!({ "v": 1 })),
+        };
+        kv.put("expired", expired).unwrap();
+        kv.put("live", live.clone()).unwrap();
+
+        assert_eq!(kv.get("expired").unwrap(), None);
+        assert_eq!(kv.get("live").unwrap(), Some(live.clone()));
+        let result = list(&kv, "", 10, None);
+        assert_eq!(names(&result), ["live"]);
+        assert_eq!(result.keys[0].expiration, live.expiration);
+        assert_eq!(result.keys[0].metadata, live.metadata);
+    }
+
+    #[test]
+    fn lists_keys_with_the_prefix_in_order() {
+        let kv = store(&["user:2", "a", "user:1", "users", "user;", "z"]);
+        let result = list(&kv, "user:", 10, None);
+        assert_eq!(names(&result), ["user:1", "user:2"]);
+        assert_eq!(result.cursor, None);
+    }
+
+    #[test]
+    fn continues_from_the_cursor() {
+        let kv = store(&["k1", "k2", "k3", "k4", "k5", "other"]);
+        let first = list(&kv, "k", 2, None);
+        assert_eq!(names(&first), ["k1", "k2"]);
+        assert_eq!(first.cursor.as_deref(), Some("k2"));
+
+        let second = list(&kv, "k", 2, first.cursor.as_deref());
+        assert_eq!(names(&second), ["k3", "k4"]);
+
+        let last = list(&kv, "k", 2, second.cursor.as_deref());
+        assert_eq!(names(&last), ["k5"]);
+        assert_eq!(last.cursor, None);
+    }
+
+    #[test]
+    fn ends_the_page_without_a_cursor_when_nothing_follows() {
+        let kv = store(&["k1", "k2"]);
+        let result = list(&kv, "k", 2, None);
+        assert_eq!(names(&result), ["k1", "k2"]);
+        assert_eq!(result.cursor, None);
+    }
+
+    #[test]
+    fn a_cursor_before_the_prefix_starts_at_the_prefix() {
+        let kv = store(&["a", "a1", "b", "b1", "b2", "c"]);
+        let result = list(&kv, "b", 10, Some("a"));
+        assert_eq!(names(&result), ["b", "b1", "b2"]);
+    }
+}
@@ -0,0 +1,320 @@
+//! A local stand-in for the Apoxy Edge runtime.
+//!
+//! Modules built by `apoxy-js` import their request/response primitives from
+//! `extism:host/user`. This crate implements those imports against in-memory
+//! messages and a pluggable [`Upstream`], so handlers can be exercised on a
+//! laptop or in CI without the proxy.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use extism::{Manifest, Plugin, UserData, Wasm};
+use js_abi::{AbiInfo, Feature, LogLevel, PhaseAbi, StartAbi};
+use serde::{Deserialize, Serialize};
+
+mod functions;
+mod kv;
+mod message;
+mod upstream;
+
+use functions::State;
+
+pub use kv::{Kv, KvEntry, MemoryKv};
+pub use message::{Headers, HttpRequest, HttpResponse};
+pub use upstream::{StubUpstream, Upstream};
+
+/// The least severe `Apoxy.log` records that are printed, if any. Info until
+/// `init_logging` says otherwise, so tests see the records too.
+static LOG_LEVEL: Mutex<Option<LogLevel>> = Mutex::new(Some(LogLevel::Info));
+
+/// Prints the module's log output to stderr at or above the given level,
+/// and its `Apoxy.log` records as lines of JSON.
+pub fn init_logging(level: &str) -> Result<()> {
+    extism::set_log_callback(|line| eprint!("{}", line), level)?;
+    *LOG_LEVEL.lock().unwrap() = match level.to_ascii_lowercase().as_str() {
+        "trace" | "debug" => Some(LogLevel::Debug),
+        "info" => Some(LogLevel::Info),
+        "warn" => Some(LogLevel::Warn),
+        "error" => Some(LogLevel::Error),
+        _ => None,
+    };
+    Ok(())
+}
+
+fn log_enabled(level: LogLevel) -> bool {
+    LOG_LEVEL.lock().unwrap().is_some_and(|min| level >= min)
+}
+
+/// Calls an export that needs no request, such as the engine's
+/// `_apoxy_compile`, without evaluating any user code first.
+pub fn call(wasm: impl Into<Vec<u8>>, name: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let upstream = StubUpstream::new(HttpResponse::default(), HashMap::new());
+    let state = UserData::new(State::new(Arc::new(upstream)));
+    let manifest = Manifest::new([Wasm::data(wasm.into())]);
+    let mut plugin = Plugin::new(&manifest, functions::all(&state), true)?;
+    let output = plugin.call::<&[u8], &[u8]>(name, input)?.to_vec();
+    Ok(output)
+}
+
+/// A shared module that an edge function imports from by name, like the
+/// engine behind modules built with `apoxy-js --dynamic`.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    pub name: String,
+    pub wasm: Vec<u8>,
+}
+
+/// A compiled edge function loaded into a Wasm engine.
+pub struct Host {
+    plugin: Plugin,
+    state: UserData<State>,
+    /// The phases the module registered handlers for, see `handle_phases`.
+    phases: Vec<String>,
+}
+
+impl Host {
+    pub fn new(
+        wasm: impl Into<Vec<u8>>,
+        provider: Option<Provider>,
+        config: impl IntoIterator<Item = (String, String)>,
+        secrets: impl IntoIterator<Item = (String, String)>,
+        upstream: Box<dyn Upstream>,
+    ) -> Result<Self> {
+        let config = config.into_iter().collect::<BTreeMap<_, _>>();
+        let mut state = State::new(Arc::from(upstream));
+        state.env_keys = config.keys().cloned().collect();
+        state.secrets = secrets.into_iter().collect();
+        let state = UserData::new(state);
+        let mut modules = vec![];
+        if let Some(provider) = provider {
+            modules.push(Wasm::data(provider.wasm).with_name(provider.name));
+        }
+        modules.push(Wasm::data(wasm.into()).with_name("main"));
+        let manifest = Manifest::new(modules).with_config(config.into_iter());
+        let mut plugin = Plugin::new(&manifest, functions::all(&state), true)?;
+        let abi = negotiate(&mut plugin)?;
+
+        // Evaluate the user's script once, the same way the proxy does before
+        // the first request.
+        if plugin.function_exists("_start") {
+            plugin.call::<&[u8], &[u8]>("_start", &[])?;
+        }
+        let phases = if abi.supports(Feature::Phases) {
+            plugin
+                .call::<&[u8], &str>("_apoxy_phases", &[])?
+                .split(',')
+                .filter(|x| !x.is_empty())
+                .map(str::to_string)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            plugin,
+            state,
+            phases,
+        })
+    }
+
+    /// Replaces the in-memory store behind `Apoxy.kv`, for example with one
+    /// seeded for a test. It is used from the next request on.
+    pub fn with_kv(self, kv: Arc<dyn Kv>) -> Result<Self> {
+        self.state.get()?.lock().unwrap().kv = kv;
+        Ok(self)
+    }
+
+    /// Runs the handler for a single request and returns the response that
+    /// would be sent downstream.
+    pub fn handle(&mut self, req: HttpRequest, backend_mode: bool) -> Result<Handled> {
+        if !self.phases.is_empty() {
+            return self.handle_phases(req, backend_mode);
+        }
+
+        let input = js_abi::encode(&StartAbi {
+            request: req.to_abi(),
+            backend_mode,
+        })?;
+
+        let state = self.state.get()?;
+        state.lock().unwrap().reset(req);
+        let error = self
+            .plugin
+            .call::<&[u8], &[u8]>("_apoxy_start", &input)
+            .err()
+            .map(|e| e.to_string());
+
+        let mut state = state.lock().unwrap();
+        let sent = state
+            .downstream
+            .take()
+            .or_else(|| state.modified_response.take())
+            .or_else(|| state.upstream_response.take());
+        let response = match (sent, &error) {
+            (Some(resp), _) => resp,
+            // The module failed before it could answer.
+            (None, Some(_)) => HttpResponse {
+                status: 500,
+                ..HttpResponse::default()
+            },
+            (None, None) if backend_mode => HttpResponse::default(),
+            // The filter let the request through untouched.
+            (None, None) => {
+                let req = state.request.clone();
+                state.upstream.send(&req)?
+            }
+        };
+
+        Ok(Handled { response, error })
+    }
+
+    /// Runs the handlers registered with `Apoxy.onRequest`, `onResponse` and
+    /// `onComplete` at the points the proxy calls them, forwarding the
+    /// request upstream in between.
+    fn handle_phases(&mut self, req: HttpRequest, backend_mode: bool) -> Result<Handled> {
+        let state = self.state.get()?;
+        state.lock().unwrap().reset(req);
+        let mut error = None;
+
+        if self.has_phase("request") {
+            let request = state.lock().unwrap().request.to_abi();
+            error = self.call(
+                "_apoxy_on_request",
+                &StartAbi {
+                    request,
+                    backend_mode,
+                },
+            )?;
+        }
+
+        let answered = {
+            let mut state = state.lock().unwrap();
+            match (state.downstream.take(), &error) {
+                (Some(resp), _) => Some(resp),
+                (None, Some(_)) => Some(HttpResponse {
+                    status: 500,
+                    ..HttpResponse::default()
+                }),
+                (None, None) if backend_mode => Some(HttpResponse::default()),
+                (None, None) => None,
+            }
+        };
+        let request = state.lock().unwrap().request.clone();
+        let response = match answered {
+            Some(resp) => resp,
+            None => {
+                let upstream = state.lock().unwrap().upstream.clone();
+                let resp = upstream.send(&request)?;
+                if self.has_phase("response") {
+                    state.lock().unwrap().upstream_response = Some(resp.clone());
+                    error = self.call(
+                        "_apoxy_on_response",
+                        &PhaseAbi {
+                            request: request.to_abi(),
+                            response: resp.to_abi(),
+                        },
+                    )?;
+                }
+                let mut state = state.lock().unwrap();
+                state.modified_response.take().unwrap_or(resp)
+            }
+        };
+
+        if self.has_phase("complete") {
+            let failed = self.call(
+                "_apoxy_on_complete",
+                &PhaseAbi {
+                    request: request.to_abi(),
+                    response: response.to_abi(),
+                },
+            )?;
+            error = error.or(failed);
+        }
+
+        Ok(Handled { response, error })
+    }
+
+    fn has_phase(&self, phase: &str) -> bool {
+        self.phases.iter().any(|x| x == phase)
+    }
+
+    /// Calls a phase export and returns why it failed, if it did.
+    fn call(&mut self, name: &str, input: &impl Serialize) -> Result<Option<String>> {
+        let input = js_abi::encode(input)?;
+        Ok(self
+            .plugin
+            .call::<&[u8], &[u8]>(name, &input)
+            .err()
+            .map(|e| e.to_string()))
+    }
+}
+
+/// Asks the module which version of the ABI it speaks and what it supports.
+fn negotiate(plugin: &mut Plugin) -> Result<AbiInfo> {
+    let abi: AbiInfo = if plugin.function_exists("_apoxy_abi") {
+        serde_json::from_slice(plugin.call::<&[u8], &[u8]>("_apoxy_abi", &[])?)?
+    } else if plugin.function_exists("_apoxy_sdk_v1alpha") {
+        bail!(
+            "The module predates `_apoxy_abi` and uses version 1 of the ABI, which this host \
+             doesn't support. Rebuild it with a current apoxy-js"
+        );
+    } else {
+        bail!("The module doesn't export `_apoxy_abi`, was it built with apoxy-js?");
+    };
+    if abi.version != js_abi::VERSION {
+        bail!(
+            "The module uses version {} of the ABI, but this host only supports version {}",
+            abi.version,
+            js_abi::VERSION
+        );
+    }
+    Ok(abi)
+}
+
+/// The outcome of running the handler for one request.
+#[derive(Debug, Clone)]
+pub struct Handled {
+    /// The response sent downstream. When the handler failed this is the
+    /// error response the module sent, or an empty 500 if it sent none.
+    pub response: HttpResponse,
+    /// Why the handler failed, if it did.
+    pub error: Option<String>,
+}
+
+/// A scripted request and upstream, as read from a JSON file by `apoxy-js run`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub backend_mode: bool,
+    pub config: BTreeMap<String, String>,
+    pub secrets: BTreeMap<String, String>,
+    /// Values `Apoxy.kv` starts out with.
+    pub kv: BTreeMap<String, String>,
+    pub request: HttpRequest,
+    pub upstream: HttpResponse,
+    pub fetch: HashMap<String, HttpResponse>,
+}
+
+impl Scenario {
+    pub fn run(self, wasm: impl Into<Vec<u8>>, provider: Option<Provider>) -> Result<Handled> {
+        let upstream = StubUpstream::new(self.upstream, self.fetch);
+        let kv = MemoryKv::new();
+        for (key, value) in self.kv {
+            let entry = KvEntry {
+                value: value.into_bytes(),
+                ..KvEntry::default()
+            };
+            kv.put(&key, entry)?;
+        }
+        let mut host = Host::new(
+            wasm,
+            provider,
+            self.config,
+            self.secrets,
+            Box::new(upstream),
+        )?
+        .with_kv(Arc::new(kv))?;
+        host.handle(self.request, self.backend_mode)
+    }
+}
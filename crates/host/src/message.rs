@@ -0,0 +1,172 @@
+use js_abi::{RequestAbi, ResponseAbi};
+use serde::{Deserialize, Serialize};
+
+pub use js_abi::Headers;
+
+/// An HTTP request as seen by the host, either the downstream request handed
+/// to the module or a request the module sends upstream.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub proto: String,
+    #[serde(with = "headers")]
+    pub headers: Headers,
+    pub host: String,
+    pub remote_addr: String,
+    #[serde(with = "body")]
+    pub body: Vec<u8>,
+}
+
+impl Default for HttpRequest {
+    fn default() -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: "/".to_string(),
+            proto: "HTTP/1.1".to_string(),
+            headers: Vec::new(),
+            host: "localhost".to_string(),
+            remote_addr: "127.0.0.1".to_string(),
+            body: Vec::new(),
+        }
+    }
+}
+
+impl HttpRequest {
+    /// The first value of the named header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// An HTTP response, either produced by the module or by the upstream.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpResponse {
+    pub status: u16,
+    #[serde(with = "headers")]
+    pub headers: Headers,
+    #[serde(with = "body")]
+    pub body: Vec<u8>,
+}
+
+impl Default for HttpResponse {
+    fn default() -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+impl HttpResponse {
+    /// The first value of the named header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+fn find_header<'a>(headers: &'a Headers, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+impl HttpRequest {
+    pub(crate) fn to_abi(&self) -> RequestAbi {
+        let (proto_major, proto_minor) = parse_proto(&self.proto);
+        RequestAbi {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            proto: self.proto.clone(),
+            proto_major,
+            proto_minor,
+            header: self.headers.clone(),
+            host: self.host.clone(),
+            remote_addr: self.remote_addr.clone(),
+            content_len: self.body.len(),
+        }
+    }
+
+    pub(crate) fn from_abi(abi: RequestAbi, body: Vec<u8>) -> Self {
+        Self {
+            method: abi.method,
+            url: abi.url,
+            proto: abi.proto,
+            headers: abi.header,
+            host: abi.host,
+            remote_addr: abi.remote_addr,
+            body,
+        }
+    }
+}
+
+impl HttpResponse {
+    pub(crate) fn to_abi(&self) -> ResponseAbi {
+        ResponseAbi {
+            status_code: self.status,
+            content_len: self.body.len(),
+            header: self.headers.clone(),
+        }
+    }
+
+    pub(crate) fn from_abi(abi: ResponseAbi, body: Vec<u8>) -> Self {
+        Self {
+            status: abi.status_code,
+            headers: abi.header,
+            body,
+        }
+    }
+}
+
+fn parse_proto(proto: &str) -> (u32, u32) {
+    let version = proto.strip_prefix("HTTP/").unwrap_or(proto);
+    let mut parts = version.split('.');
+    let major = parts.next().and_then(|x| x.parse().ok()).unwrap_or(1);
+    let minor = parts.next().and_then(|x| x.parse().ok()).unwrap_or(0);
+    (major, minor)
+}
+
+/// Bodies are written as UTF-8 strings in scenario files.
+mod body {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Ok(String::deserialize(deserializer)?.into_bytes())
+    }
+}
+
+/// Scenario files may write headers either as a list of `[name, value]`
+/// pairs or, when no name repeats, as an object.
+mod headers {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Headers;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        List(Headers),
+        Map(BTreeMap<String, String>),
+    }
+
+    pub fn serialize<S: Serializer>(headers: &Headers, serializer: S) -> Result<S::Ok, S::Error> {
+        headers.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Headers, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::List(headers) => headers,
+            Repr::Map(headers) => headers.into_iter().collect(),
+        })
+    }
+}
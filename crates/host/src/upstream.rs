@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::message::{HttpRequest, HttpResponse};
+
+/// Where the host sends requests that leave the module. Outbound `fetch`
+/// calls run on their own threads so that several can be in flight at once.
+pub trait Upstream: Send + Sync {
+    /// Forwards the request to the upstream, either because the handler
+    /// called `req.next()` or because a filter let the request through.
+    fn send(&self, req: &HttpRequest) -> Result<HttpResponse>;
+
+    /// Performs an outbound `fetch` issued by the handler.
+    fn fetch(&self, req: &HttpRequest) -> Result<HttpResponse>;
+}
+
+/// An upstream that answers with scripted responses and never touches the
+/// network.
+#[derive(Debug, Clone, Default)]
+pub struct StubUpstream {
+    pub response: HttpResponse,
+    pub fetch: HashMap<String, HttpResponse>,
+}
+
+impl StubUpstream {
+    pub fn new(response: HttpResponse, fetch: HashMap<String, HttpResponse>) -> Self {
+        Self { response, fetch }
+    }
+}
+
+impl Upstream for StubUpstream {
+    fn send(&self, _req: &HttpRequest) -> Result<HttpResponse> {
+        Ok(self.response.clone())
+    }
+
+    fn fetch(&self, req: &HttpRequest) -> Result<HttpResponse> {
+        self.fetch
+            .get(&req.url)
+            .cloned()
+            .ok_or_else(|| anyhow!("no stubbed response for {} {}", req.method, req.url))
+    }
+}
@@ -0,0 +1,169 @@
+//! Runs scenarios against small hand-written modules that speak the ABI the
+//! way the engine does, without needing a built engine.
+
+use js_abi::ResponseAbi;
+use js_host::{HttpRequest, HttpResponse, Scenario};
+
+/// Escapes bytes for a WAT data segment.
+fn data(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("\\{:02x}", x)).collect()
+}
+
+/// A module that reports `version` from `_apoxy_abi` and whose handler sends
+/// `downstream`, or lets the request through if there is none.
+fn module(version: u32, downstream: Option<&HttpResponse>) -> Vec<u8> {
+    let abi = format!(r#"{{"version":{},"features":[]}}"#, version);
+    let (head, body) = match downstream {
+        Some(resp) => (
+            js_abi::encode(&ResponseAbi {
+                status_code: resp.status,
+                content_len: resp.body.len(),
+                header: resp.headers.clone(),
+            })
+            .unwrap(),
+            resp.body.clone(),
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+    let start = match downstream {
+        Some(_) => format!(
+            "(drop (call $send_downstream (call $copy (i32.const 1024) (i32.const {})) \
+             (call $copy (i32.const 2048) (i32.const {}))))",
+            head.len(),
+            body.len()
+        ),
+        None => String::new(),
+    };
+    format!(
+        r#"
+        (module
+          (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+          (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+          (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+          (import "extism:host/user" "_apoxy_send_downstream"
+            (func $send_downstream (param i64 i64) (result i64)))
+          (memory 1)
+          (data (i32.const 0) "{abi}")
+          (data (i32.const 1024) "{head}")
+          (data (i32.const 2048) "{body}")
+          ;; Copies bytes of this module's memory into an Extism block.
+          (func $copy (param $ptr i32) (param $len i32) (result i64)
+            (local $offs i64) (local $i i32)
+            (local.set $offs (call $alloc (i64.extend_i32_u (local.get $len))))
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+                (call $store_u8
+                  (i64.add (local.get $offs) (i64.extend_i32_u (local.get $i)))
+                  (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+            (local.get $offs))
+          (func (export "_apoxy_abi") (result i32)
+            (call $output_set (call $copy (i32.const 0) (i32.const {abi_len})) (i64.const {abi_len}))
+            (i32.const 0))
+          (func (export "_apoxy_start") (result i32)
+            {start}
+            (i32.const 0)))
+        "#,
+        abi = data(abi.as_bytes()),
+        abi_len = abi.len(),
+        head = data(&head),
+        body = data(&body),
+        start = start,
+    )
+    .into_bytes()
+}
+
+fn upstream() -> HttpResponse {
+    HttpResponse {
+        status: 200,
+        headers: vec![
+            ("set-cookie".into(), "a=1".into()),
+            ("set-cookie".into(), "b=2".into()),
+        ],
+        body: b"hello from upstream".to_vec(),
+    }
+}
+
+#[test]
+fn passes_the_request_through_to_the_upstream() {
+    let scenario = Scenario {
+        upstream: upstream(),
+        ..Scenario::default()
+    };
+    let handled = scenario.run(module(js_abi::VERSION, None), None).unwrap();
+    assert_eq!(handled.error, None);
+    assert_eq!(handled.response, upstream());
+}
+
+#[test]
+fn answers_backend_requests_without_a_response_with_an_empty_200() {
+    let scenario = Scenario {
+        backend_mode: true,
+        upstream: upstream(),
+        ..Scenario::default()
+    };
+    let handled = scenario.run(module(js_abi::VERSION, None), None).unwrap();
+    assert_eq!(handled.response, HttpResponse::default());
+}
+
+#[test]
+fn returns_the_response_the_handler_sent() {
+    let sent = HttpResponse {
+        status: 403,
+        headers: vec![("content-type".into(), "text/plain".into())],
+        body: b"forbidden".to_vec(),
+    };
+    let scenario = Scenario {
+        request: HttpRequest {
+            url: "/admin".into(),
+            ..HttpRequest::default()
+        },
+        upstream: upstream(),
+        ..Scenario::default()
+    };
+    let handled = scenario
+        .run(module(js_abi::VERSION, Some(&sent)), None)
+        .unwrap();
+    assert_eq!(handled.error, None);
+    assert_eq!(handled.response, sent);
+}
+
+#[test]
+fn rejects_modules_built_for_another_abi_version() {
+    let err = Scenario::default()
+        .run(module(js_abi::VERSION + 1, None), None)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("version"), "{}", err);
+}
+
+#[test]
+fn rejects_version_1_modules() {
+    let v1 = r#"
+        (module
+          (func (export "_apoxy_sdk_v1alpha"))
+          (func (export "_apoxy_start") (result i32) (i32.const 0)))
+    "#;
+    let err = Scenario::default().run(v1, None).err().unwrap();
+    assert!(err.to_string().contains("version 1"), "{}", err);
+}
+
+#[test]
+fn reads_headers_as_an_object_or_as_pairs() {
+    let scenario: Scenario = serde_json::from_str(
+        r#"{
+            "request": { "headers": { "accept": "text/html" } },
+            "upstream": { "headers": [["set-cookie", "a=1"], ["set-cookie", "b=2"]] }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        scenario.request.headers,
+        vec![("accept".to_string(), "text/html".to_string())]
+    );
+    assert_eq!(scenario.request.method, "GET");
+    assert_eq!(scenario.upstream.headers, upstream().headers);
+    assert_eq!(scenario.upstream.header("Set-Cookie"), Some("a=1"));
+}